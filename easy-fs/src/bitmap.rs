@@ -72,6 +72,101 @@ impl Bitmap {
                 bitmap_block[bits64_pos] -= 1u64 << inner_pos;
             });
     }
+    /// Find `count` consecutive free bits, without allocating them.
+    ///
+    /// Scans the whole region as one flat bitstream, carrying the current
+    /// run length across `u64` words and block boundaries, so a run that
+    /// straddles either isn't missed. Returns `None` (mutating nothing) if
+    /// no run of that length exists.
+    fn find_contiguous(&self, block_device: &Arc<dyn BlockDevice>, count: usize) -> Option<usize> {
+        let mut run_start = 0usize;
+        let mut run_len = 0usize;
+        for block_id in 0..self.blocks {
+            let found = get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |bitmap_block: &BitmapBlock| {
+                    for (bits64_pos, bits64) in bitmap_block.iter().enumerate() {
+                        for inner_pos in 0..64 {
+                            let bit = block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos;
+                            if bits64 & (1u64 << inner_pos) == 0 {
+                                if run_len == 0 {
+                                    run_start = bit;
+                                }
+                                run_len += 1;
+                                if run_len == count {
+                                    return Some(run_start);
+                                }
+                            } else {
+                                run_len = 0;
+                            }
+                        }
+                    }
+                    None
+                });
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+    /// Flip `count` bits starting at `start` to `value` (`true` = allocated),
+    /// touching each affected cache block via `modify`.
+    fn set_contiguous(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        start: usize,
+        count: usize,
+        value: bool,
+    ) {
+        let mut bit = start;
+        let mut remaining = count;
+        while remaining > 0 {
+            let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+            let bits_left_in_block = BLOCK_BITS - (bits64_pos * 64 + inner_pos);
+            let take = remaining.min(bits_left_in_block);
+            get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .modify(0, |bitmap_block: &mut BitmapBlock| {
+                    let mut b = bits64_pos;
+                    let mut p = inner_pos;
+                    for _ in 0..take {
+                        if value {
+                            bitmap_block[b] |= 1u64 << p;
+                        } else {
+                            assert!(bitmap_block[b] & (1u64 << p) > 0);
+                            bitmap_block[b] -= 1u64 << p;
+                        }
+                        p += 1;
+                        if p == 64 {
+                            p = 0;
+                            b += 1;
+                        }
+                    }
+                });
+            bit += take;
+            remaining -= take;
+        }
+    }
+    /// Allocate `count` consecutive blocks, returning the starting index, or
+    /// `None` (mutating nothing) if no run that long is free.
+    pub fn alloc_contiguous(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        count: usize,
+    ) -> Option<usize> {
+        let start = self.find_contiguous(block_device, count)?;
+        self.set_contiguous(block_device, start, count, true);
+        Some(start)
+    }
+    /// Deallocate the `count` consecutive blocks starting at `start`.
+    pub fn dealloc_contiguous(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        start: usize,
+        count: usize,
+    ) {
+        self.set_contiguous(block_device, start, count, false);
+    }
     /// Get the max number of allocatable blocks
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
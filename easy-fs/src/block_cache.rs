@@ -103,55 +103,79 @@ const BLOCK_CACHE_SIZE: usize = 16;
 /// 当我们要对一个磁盘块进行读写时，块缓存全局管理器检查它是否已经被载入内存中，
 /// 如果是则直接返回，否则就读取磁盘块到内存。
 /// 如果内存中驻留的磁盘块缓冲区的数量已满，则需要进行缓存替换。
-/// 这里使用一种类`FIFO`的缓存替换算法，在管理器中只需维护一个队列
+/// 这里使用`CLOCK`（第二次机会）算法近似`LRU`：每个槽位除了块编号和块缓存之外，
+/// 还带有一个引用位`referenced`，`hand`指向下一次扫描要检查的槽位。
 pub struct BlockCacheManager {
-    /// 维护块编号和块缓存的二元组
+    /// 维护块编号、块缓存与引用位的三元组
     /// 块缓存的类型是一个`Arc<Mutex<BlockCache>>`，这是 Rust 中的经典组合，它可以同时提供共享引用和互斥访问。
     /// 这里的共享引用意义在于块缓存既需要在管理器`BlockCacheManager`保留一个引用，还需要将引用返回给块缓存的请求者。
     /// 而互斥访问在单核上的意义在于提供内部可变性通过编译，在多核环境下则可以帮助我们避免可能的并发冲突。
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>, bool)>,
+    /// CLOCK替换算法的扫描指针，指向`queue`中下一个将被检查的槽位
+    hand: usize,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
         Self {
             queue: VecDeque::new(),
+            hand: 0,
         }
     }
 
+    // NOTE: 只有`queue`已满（`== BLOCK_CACHE_SIZE`）时才会被调用。沿着`hand`
+    // 顺时针扫描：强引用计数`> 1`说明这个块缓存还在被外部使用，跳过；
+    // 引用位为真，说明它最近被访问过，给它“第二次机会”（清除引用位后跳过）；
+    // 否则就是可以替换的槽位。如果转了不止一圈还没找到（所有槽位都被钉住），
+    // 说明缓存确实耗尽了，直接`panic`。
+    /// Find the index of a slot to evict via the CLOCK algorithm, advancing
+    /// `hand` as it scans. Panics if every slot is still pinned (strong
+    /// count > 1).
+    fn clock_evict(&mut self) -> usize {
+        let len = self.queue.len();
+        // Two full sweeps: the first may only clear reference bits.
+        for _ in 0..2 * len {
+            let idx = self.hand;
+            self.hand = (self.hand + 1) % len;
+            let (_, cache, referenced) = &mut self.queue[idx];
+            if Arc::strong_count(cache) > 1 {
+                continue;
+            }
+            if *referenced {
+                *referenced = false;
+                continue;
+            }
+            return idx;
+        }
+        panic!("Run out of BlockCache!");
+    }
+
     /// 尝试从块缓存管理器中获取一个编号为 block_id 的块缓存，如果找不到的话会读取磁盘，还有可能会发生缓存替换
     pub fn get_block_cache(
         &mut self,
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        // 遍历整个队列试图找到一个编号相同的块缓存，如果找到，将块缓存管理器中保存的块缓存的引用复制一份并返回
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
+        // 遍历整个队列试图找到一个编号相同的块缓存，如果找到，将引用位置位并复制一份返回
+        if let Some(pair) = self.queue.iter_mut().find(|pair| pair.0 == block_id) {
+            pair.2 = true;
             Arc::clone(&pair.1)
         } else {
             // 此时必须将块从磁盘读入内存中的缓冲区。读取前需要判断已保存的块数量是否达到了上限。
-            // 是，则执行缓存替换算法，替换的标准是其强引用计数=1 ，即除了块缓存管理器保留的一份副本之外，在外面没有副本正在使用。
-            // Substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // From front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
-            }
-            // Load block into mem and push back
-            // 创建一个新的块缓存（会触发`read_block`进行块读取）并加入到队尾，最后返回给请求着。
+            // Load block into mem
+            // 创建一个新的块缓存（会触发`read_block`进行块读取）
             let block_cache = Arc::new(Mutex::new(BlockCache::new(
                 block_id,
                 Arc::clone(&block_device),
             )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
+            if self.queue.len() == BLOCK_CACHE_SIZE {
+                // Substitute via CLOCK, in place so `hand` stays meaningful
+                let idx = self.clock_evict();
+                self.queue[idx] = (block_id, Arc::clone(&block_cache), false);
+            } else {
+                self.queue
+                    .push_back((block_id, Arc::clone(&block_cache), false));
+            }
             block_cache
         }
     }
@@ -174,7 +198,7 @@ pub fn get_block_cache(
 /// Sync all block cache to block device
 pub fn block_cache_sync_all() {
     let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
+    for (_, cache, _) in manager.queue.iter() {
         cache.lock().sync();
     }
 }
@@ -204,6 +204,65 @@ impl Inode {
         block_cache_sync_all();
     }
 
+    /// Get the size of the file represented by this `inode`, in bytes
+    pub fn size(&self) -> u32 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size)
+    }
+
+    /// Whether this `inode` is a directory
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    /// Create a directory `inode` under current `inode` by name
+    /// 和`create`几乎一样，只是新`inode`按`DiskInodeType::Directory`初始化
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            // Assert it is a directory
+            assert!(root_inode.is_dir());
+            // Has the file been created?
+            self.find_inode_id(name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        // Alloc a `inode` with an indirect block
+        let new_inode_id = fs.alloc_inode();
+        // Initialize `inode`
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(DiskInodeType::Directory);
+            });
+        self.modify_disk_inode(|root_inode| {
+            // Append directory in the dirent
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            // Increase size
+            self.increase_size(new_size as u32, root_inode, &mut fs);
+            // Write dirent
+            let dirent = DirEntry::new(name, new_inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+        });
+
+        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        block_cache_sync_all();
+        Some(Arc::new(Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        )))
+    }
+
     /// Implement in [CH6]
     /// Create hard link, only ROOT_NODE can call it
     pub fn link(&self, old: &str, new: &str) -> Option<Arc<Inode>> {
@@ -70,6 +70,27 @@ impl OSInode {
         let inner = self.inner.exclusive_access();
         (inner.inode.block_id, inner.inode.block_offset)
     }
+
+    /// Current seek offset, used by `sys_lseek`'s `SeekFrom::Current`
+    pub fn get_offset(&self) -> usize {
+        let inner = self.inner.exclusive_access();
+        inner.offset
+    }
+    /// Set the seek offset directly, used by `sys_lseek`
+    pub fn set_offset(&self, offset: usize) {
+        let mut inner = self.inner.exclusive_access();
+        inner.offset = offset;
+    }
+    /// Size of the underlying file in bytes, used by `sys_lseek`'s `SeekFrom::End`
+    pub fn size(&self) -> u32 {
+        let inner = self.inner.exclusive_access();
+        inner.inode.size()
+    }
+    /// Whether the underlying inode is a directory, used by `sys_fstat`
+    pub fn is_dir(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        inner.inode.is_dir()
+    }
 }
 
 lazy_static! {
@@ -104,6 +125,8 @@ bitflags! {
         const CREATE = 1 << 9;
         /// Truncate file size to 0
         const TRUNC = 1 << 10;
+        /// Resolved path must name a directory
+        const DIRECTORY = 1 << 11;
     }
 }
 
@@ -123,30 +146,62 @@ impl OpenFlags {
     }
 }
 
+// NOTE: 原来这里只会在`ROOT_INODE`下`find`一层，路径里出现的`/`完全没有
+// 意义；现在把路径按`/`切分，从`ROOT_INODE`开始逐级`find`，并要求除最后
+// 一级之外的每一级都是目录，这样路径才能表示真正的层级结构。
+/// Walk `path` component-by-component from `ROOT_INODE`, requiring every
+/// intermediate component to resolve to a directory. Returns the parent
+/// directory `inode` and the final path component's name.
+fn resolve_parent(path: &str) -> Option<(Arc<Inode>, &str)> {
+    let mut components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    let name = components.pop()?;
+    let mut cur = ROOT_INODE.clone();
+    for component in components {
+        cur = cur.find(component)?;
+        if !cur.is_dir() {
+            return None;
+        }
+    }
+    Some((cur, name))
+}
+
 /// Open a file
 /// 这里主要是实现了`OpenFlags`各标志位的语义。
 /// 例如只有`flags`参数包含`CREATE`标志位才允许创建文件；
 /// 而如果文件已经存在，则清空文件的内容。
 pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
+    let (parent, name) = resolve_parent(name)?;
+    let inode = if flags.contains(OpenFlags::CREATE) {
+        if let Some(inode) = parent.find(name) {
             // Clear size
             inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
+            inode
         } else {
             // Create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+            parent.create(name)?
         }
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        let inode = parent.find(name)?;
+        if flags.contains(OpenFlags::TRUNC) {
+            inode.clear();
+        }
+        inode
+    };
+    if flags.contains(OpenFlags::DIRECTORY) && !inode.is_dir() {
+        return None;
+    }
+    Some(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// Create a directory at `path`, requiring every intermediate component
+/// (and the parent of the final component) to already exist as a directory.
+/// Returns `false` if the path is invalid or the final component already
+/// exists.
+pub fn mkdir(path: &str) -> bool {
+    match resolve_parent(path) {
+        Some((parent, name)) => parent.mkdir(name).is_some(),
+        None => false,
     }
 }
 
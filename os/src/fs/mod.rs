@@ -1,6 +1,7 @@
 //! File trait & inode(dir, file, pipe, stdin, stdout)
 
 mod inode;
+mod pipe;
 mod stdio;
 
 use core::any::Any;
@@ -34,6 +35,49 @@ pub trait File: Send + Sync + AToAny {
     /// Write to the file from buf, return the number of bytes written
     /// 将缓冲区中的数据写入文件,最多将缓冲区中的数据全部写入,并返回直接写入的字节数
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Check readiness without committing to a blocking `read`/`write`.
+    /// Default: ready for whatever `readable`/`writable` allow, never `HUP`.
+    /// Types that can actually be empty/full (e.g. `Pipe`) should override this.
+    fn poll(&self) -> PollFlags {
+        let mut flags = PollFlags::empty();
+        if self.readable() {
+            flags |= PollFlags::READABLE;
+        }
+        if self.writable() {
+            flags |= PollFlags::WRITABLE;
+        }
+        flags
+    }
+    /// Like [`File::poll`], but if none of `interest` is currently ready,
+    /// atomically (i.e. without releasing whatever lock backs this check in
+    /// between) register the current task to be woken once it might be.
+    /// Used by `sys_poll` so it can block on a real wait queue instead of
+    /// busy-polling. Default: just `poll()` - correct for types that are
+    /// always ready and so never need to park a waiter (`Stdin`/`Stdout`/
+    /// `OSInode`). Types with an actual wait queue (e.g. `Pipe`) override
+    /// both this and `unregister_waiter`.
+    fn poll_or_register(&self, interest: PollFlags) -> PollFlags {
+        let _ = interest;
+        self.poll()
+    }
+    /// Undo whatever registration the last `poll_or_register` call made for
+    /// the current task, if any. `sys_poll` calls this on every watched fd
+    /// once the task wakes, so a task parked across several fds by the same
+    /// `sys_poll` call never stays registered on the ones that *didn't*
+    /// become ready. Default: no-op, pairing with the default `poll_or_register`.
+    fn unregister_waiter(&self) {}
+}
+
+bitflags! {
+    /// Readiness bits returned by [`File::poll`]
+    pub struct PollFlags: u8 {
+        /// Data is available to `read` without blocking
+        const READABLE = 1 << 0;
+        /// There is room to `write` without blocking
+        const WRITABLE = 1 << 1;
+        /// The peer has hung up (e.g. a pipe whose write ends are all closed)
+        const HUP = 1 << 2;
+    }
 }
 
 /// The stat of a inode
@@ -68,5 +112,6 @@ bitflags! {
 }
 
 /// Change in [CH6]
-pub use inode::{list_apps, open_file, OSInode, OpenFlags, ROOT_INODE};
+pub use inode::{list_apps, mkdir, open_file, OSInode, OpenFlags, ROOT_INODE};
+pub use pipe::{make_pipe, Pipe};
 pub use stdio::{Stdin, Stdout};
@@ -1,9 +1,9 @@
-use super::File;
+use super::{File, PollFlags};
 use crate::mm::UserBuffer;
 use crate::sync::UPSafeCell;
 use alloc::sync::{Arc, Weak};
 
-use crate::task::suspend_current_and_run_next;
+use crate::task::{block_current_and_run_next, current_task, WaitQueue};
 
 /// `IPC` pipe
 pub struct Pipe {
@@ -64,6 +64,10 @@ pub struct PipeRingBuffer {
     /// 保存了它的写端的一个弱引用计数，
     /// 这是由于在某些情况下需要确认该管道所有的写端是否都已经被关闭了，通过这个字段很容易确认这一点
     write_end: Option<Weak<Pipe>>,
+    /// 阻塞在"管道为空"上等待可读数据的任务
+    readers: WaitQueue,
+    /// 阻塞在"管道已满"上等待可写空间的任务
+    writers: WaitQueue,
 }
 
 impl PipeRingBuffer {
@@ -75,6 +79,8 @@ impl PipeRingBuffer {
             tail: 0,
             status: RingBufferStatus::Empty,
             write_end: None,
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
         }
     }
     /// 调用`PipeRingBuffer::set_write_end`在管道中保留它的写端的弱引用计数
@@ -82,24 +88,35 @@ impl PipeRingBuffer {
         self.write_end = Some(Arc::downgrade(write_end));
     }
 
+    /// 写入一个字节；如果这次写入使管道从`Empty`变为`Normal`，说明有新数据
+    /// 到来，唤醒所有等待读取的任务
     pub fn write_byte(&mut self, byte: u8) {
+        let was_empty = self.status == RingBufferStatus::Empty;
         self.status = RingBufferStatus::Normal;
         self.arr[self.tail] = byte;
         self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
         if self.tail == self.head {
             self.status = RingBufferStatus::Full;
         }
+        if was_empty {
+            self.readers.wake_all();
+        }
     }
 
     /// 可以从管道中读取一个字节，注意在调用它之前需要确保管道缓冲区中不是空的
     /// 它会更新循环队列队头的位置，并比较队头和队尾是否相同，如果相同的话则说明管道的状态变为空`EMPTY`
+    /// 如果这次读取使管道从`Full`变为`Normal`，说明腾出了写入空间，唤醒所有等待写入的任务
     pub fn read_byte(&mut self) -> u8 {
+        let was_full = self.status == RingBufferStatus::Full;
         self.status = RingBufferStatus::Normal;
         let c = self.arr[self.head];
         self.head = (self.head + 1) % RING_BUFFER_SIZE;
         if self.head == self.tail {
             self.status = RingBufferStatus::Empty;
         }
+        if was_full {
+            self.writers.wake_all();
+        }
         c
     }
 
@@ -141,6 +158,19 @@ pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     (read_end, write_end)
 }
 
+// NOTE: 如果这是管道的最后一个写端，读端可能正阻塞等待新数据，
+// 但既然不会再有数据写入了，需要唤醒它们，让它们观察到`all_write_ends_closed`
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if self.writable {
+            let mut ring_buffer = self.buffer.exclusive_access();
+            if ring_buffer.all_write_ends_closed() {
+                ring_buffer.readers.wake_all();
+            }
+        }
+    }
+}
+
 impl File for Pipe {
     fn readable(&self) -> bool {
         self.readable
@@ -148,6 +178,55 @@ impl File for Pipe {
     fn writable(&self) -> bool {
         self.writable
     }
+    // NOTE: 不像`read`/`write`那样需要真正消费数据，`poll`只是看一眼当前状态，
+    // 给`sys_poll`这样的调用者一个"要不要阻塞"的依据
+    fn poll(&self) -> PollFlags {
+        let ring_buffer = self.buffer.exclusive_access();
+        let mut flags = PollFlags::empty();
+        if self.readable && ring_buffer.available_read() > 0 {
+            flags |= PollFlags::READABLE;
+        }
+        if self.writable && ring_buffer.available_write() > 0 {
+            flags |= PollFlags::WRITABLE;
+        }
+        if ring_buffer.all_write_ends_closed() {
+            flags |= PollFlags::HUP;
+        }
+        flags
+    }
+    // NOTE: 检查和登记必须在同一次`exclusive_access()`持锁期间内完成，否则
+    // 检查到"没就绪"和真正挂到`readers`/`writers`等待队列之间，写入者/读取者
+    // 可能正好抢先完成了一次读写并调用了`wake_all`，这次唤醒就会被错过，
+    // 导致当前任务一直挂在队列里等不到下一次唤醒——和`read`/`write`自己
+    // 挂起前先检查、再登记、再释放锁的做法是同一个道理。
+    fn poll_or_register(&self, interest: PollFlags) -> PollFlags {
+        let mut ring_buffer = self.buffer.exclusive_access();
+        let mut flags = PollFlags::empty();
+        if self.readable && ring_buffer.available_read() > 0 {
+            flags |= PollFlags::READABLE;
+        }
+        if self.writable && ring_buffer.available_write() > 0 {
+            flags |= PollFlags::WRITABLE;
+        }
+        if ring_buffer.all_write_ends_closed() {
+            flags |= PollFlags::HUP;
+        }
+        if (flags & (interest | PollFlags::HUP)).is_empty() {
+            if interest.contains(PollFlags::READABLE) {
+                ring_buffer.readers.park_current();
+            }
+            if interest.contains(PollFlags::WRITABLE) {
+                ring_buffer.writers.park_current();
+            }
+        }
+        flags
+    }
+    fn unregister_waiter(&self) {
+        let mut ring_buffer = self.buffer.exclusive_access();
+        let task = current_task().unwrap();
+        ring_buffer.readers.remove(&task);
+        ring_buffer.writers.remove(&task);
+    }
     fn read(&self, buf: UserBuffer) -> usize {
         assert!(self.readable());
         let want_to_read = buf.len();
@@ -167,9 +246,13 @@ impl File for Pipe {
                 if ring_buffer.all_write_ends_closed() {
                     return already_read;
                 }
-                // 否则调用suspend_current_and_run_next切换到其他任务，等切换回来之后回到循环开头，再看一下有没有字符了
-                drop(ring_buffer); // 手动释放管道自身的锁，因为切换任务的`__switch`不是一个正常的函数调用
-                suspend_current_and_run_next();
+                // 否则将当前任务登记到管道的读等待队列上，释放管道自身的锁之后
+                // 再切换到其他任务，等有新数据写入或者所有写端关闭时再被唤醒。
+                // 注意必须先`drop(ring_buffer)`才能调用`block_current_and_run_next`，
+                // 否则管道的锁会在任务被挂起期间一直被占用
+                ring_buffer.readers.park_current();
+                drop(ring_buffer);
+                block_current_and_run_next();
                 continue;
             }
             // loop_read不是0,说明有loop_read个字节可以读取，
@@ -200,8 +283,9 @@ impl File for Pipe {
             let mut ring_buffer = self.buffer.exclusive_access();
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                ring_buffer.writers.park_current();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                block_current_and_run_next();
                 continue;
             }
             // Write at most loop_write bytes
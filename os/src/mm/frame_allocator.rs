@@ -69,6 +69,24 @@ impl StackFrameAllocator {
         self.end = r.0;
         // trace!("last {} Physical Frames.", self.end - self.current);
     }
+
+    // NOTE: 只给`BuddyFrameAllocator`在桶里找不到合适的`span`时用来"开荒"：
+    // 直接从`current`往上推进`count`个页，并且让起始页号按`align`对齐，
+    // 这样`buddy`算法里用异或找伙伴块的技巧才是正确的。
+    // 它绕开了`recycled`栈——那些被回收的单页彼此之间不保证连续，不能拿来拼`span`。
+    /// Bump-allocate `count` frames directly from the unused region, with the
+    /// returned base page number aligned to `align` frames. Used by
+    /// [`BuddyFrameAllocator`] to refill a size class; never touches
+    /// `recycled`, since recycled single frames aren't guaranteed contiguous.
+    fn alloc_aligned(&mut self, count: usize, align: usize) -> Option<usize> {
+        let base = (self.current + align - 1) / align * align;
+        if base + count > self.end {
+            None
+        } else {
+            self.current = base + count;
+            Some(base)
+        }
+    }
 }
 impl FrameAllocator for StackFrameAllocator {
     // NOTE: 通过 FrameAllocator 的 new 方法创建实例的时候，
@@ -110,7 +128,139 @@ impl FrameAllocator for StackFrameAllocator {
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+// NOTE: 最大支持 2^MAX_ORDER 个连续页帧（16MiB）的一次性分配，
+// 超过这个数量的请求直接失败，避免 free_lists 数组无限增长
+const MAX_ORDER: usize = 12;
+
+// NOTE: 借鉴 tcmalloc 的 size-class 思路：空闲的连续页帧区间（span）按照
+// 2 的幂次分桶保存在 free_lists[order] 里，每个元素是该 span 的起始页号。
+// 分配时从能满足请求的最小桶里弹出，不够就向上一级桶借一个大 span 再拆开；
+// 回收时检查"伙伴"（地址上相邻、大小相同的另一半）是否也空闲，空闲就合并成上一级的 span。
+/// A buddy allocator layered over [`StackFrameAllocator`]'s bump region,
+/// used to satisfy requests for physically contiguous frame ranges.
+/// Single-frame allocation still goes through `StackFrameAllocator` directly.
+struct BuddyFrameAllocator {
+    /// `free_lists[order]` holds the starting ppn of every free span of size
+    /// `2^order` frames
+    free_lists: [Vec<usize>; MAX_ORDER + 1],
+    /// currently allocated `(base, count)` ranges, used to reject double frees
+    allocated: Vec<(usize, usize)>,
+}
+
+impl BuddyFrameAllocator {
+    fn new() -> Self {
+        Self {
+            free_lists: core::array::from_fn(|_| Vec::new()),
+            allocated: Vec::new(),
+        }
+    }
+
+    /// smallest order such that `2^order >= count`
+    fn order_for(count: usize) -> usize {
+        let mut order = 0;
+        while (1usize << order) < count {
+            order += 1;
+        }
+        order
+    }
+
+    /// Pop a free span of exactly `2^order` frames, splitting a span one
+    /// order up if this bucket is empty. Does not reach out to the bump
+    /// allocator; returns `None` if no free span of `order` or higher exists.
+    fn pop_span(&mut self, order: usize) -> Option<usize> {
+        if order > MAX_ORDER {
+            return None;
+        }
+        if let Some(base) = self.free_lists[order].pop() {
+            return Some(base);
+        }
+        let upper = self.pop_span(order + 1)?;
+        let buddy = upper + (1usize << order);
+        self.free_lists[order].push(buddy);
+        Some(upper)
+    }
+
+    /// Return a `2^order`-frame span to the free lists, coalescing with its
+    /// buddy (and the buddy's buddy, ...) as far as possible
+    fn push_span(&mut self, base: usize, order: usize) {
+        if order >= MAX_ORDER {
+            self.free_lists[order].push(base);
+            return;
+        }
+        let buddy = base ^ (1usize << order);
+        if let Some(pos) = self.free_lists[order].iter().position(|&b| b == buddy) {
+            self.free_lists[order].swap_remove(pos);
+            self.push_span(base.min(buddy), order + 1);
+        } else {
+            self.free_lists[order].push(base);
+        }
+    }
+}
+
+/// Composite allocator: single-frame alloc/dealloc still goes through
+/// `StackFrameAllocator` unmodified; a [`BuddyFrameAllocator`] tier on top
+/// handles requests for physically contiguous multi-frame ranges.
+pub struct FrameAllocatorImpl {
+    single: StackFrameAllocator,
+    buddy: BuddyFrameAllocator,
+}
+
+impl FrameAllocatorImpl {
+    fn new() -> Self {
+        Self {
+            single: StackFrameAllocator::new(),
+            buddy: BuddyFrameAllocator::new(),
+        }
+    }
+
+    fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.single.init(l, r);
+    }
+
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        self.single.alloc()
+    }
+
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        self.single.dealloc(ppn);
+    }
+
+    /// Allocate `count` physically contiguous frames, returning the base ppn
+    fn alloc_contiguous(&mut self, count: usize) -> Option<usize> {
+        let order = BuddyFrameAllocator::order_for(count);
+        if order > MAX_ORDER {
+            return None;
+        }
+        let span_size = 1usize << order;
+        let base = match self.buddy.pop_span(order) {
+            Some(base) => base,
+            None => self.single.alloc_aligned(span_size, span_size)?,
+        };
+        self.buddy.allocated.push((base, span_size));
+        Some(base)
+    }
+
+    /// Deallocate a `count`-frame range previously returned by `alloc_contiguous`
+    fn dealloc_contiguous(&mut self, base: usize, count: usize) {
+        let order = BuddyFrameAllocator::order_for(count);
+        let span_size = 1usize << order;
+        let pos = self
+            .buddy
+            .allocated
+            .iter()
+            .position(|&(b, c)| b == base && c == span_size);
+        match pos {
+            Some(idx) => {
+                self.buddy.allocated.swap_remove(idx);
+            }
+            None => panic!(
+                "Frame range base={:#x} count={} has not been allocated or was already freed!",
+                base, count
+            ),
+        }
+        self.buddy.push_span(base, order);
+    }
+}
 
 // NOTE: 全局实例 FRAME_ALLOCATOR
 lazy_static! {
@@ -143,6 +293,45 @@ pub fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
+/// tracker for a physically contiguous run of `count` page frames,
+/// allocated by [`frame_alloc_contiguous`]
+pub struct FrameRangeTracker {
+    /// physical page number of the first frame in the range
+    pub ppn_base: PhysPageNum,
+    /// number of frames in the range
+    pub count: usize,
+}
+
+impl FrameRangeTracker {
+    fn new(ppn_base: PhysPageNum, count: usize) -> Self {
+        // page cleaning
+        for i in 0..count {
+            let bytes_array = PhysPageNum::from(ppn_base.0 + i).get_bytes_array();
+            for byte in bytes_array {
+                *byte = 0;
+            }
+        }
+        Self { ppn_base, count }
+    }
+}
+
+impl Drop for FrameRangeTracker {
+    fn drop(&mut self) {
+        FRAME_ALLOCATOR
+            .exclusive_access()
+            .dealloc_contiguous(self.ppn_base.0, self.count);
+    }
+}
+
+/// Allocate `count` physically contiguous page frames in FrameRangeTracker
+/// style, e.g. for DMA buffers or multi-page kernel structures
+pub fn frame_alloc_contiguous(count: usize) -> Option<FrameRangeTracker> {
+    FRAME_ALLOCATOR
+        .exclusive_access()
+        .alloc_contiguous(count)
+        .map(|base| FrameRangeTracker::new(base.into(), count))
+}
+
 #[allow(unused)]
 /// a simple test for frame allocator
 pub fn frame_allocator_test() {
@@ -1,9 +1,11 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
 use super::{frame_alloc, FrameTracker, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use crate::sync::UPSafeCell;
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
+use lazy_static::*;
 
 // NOTE: 实现页表项中的标志位`PTEFlags`
 // bitflags 是一个 Rust 中常用来比特标志位的 crate，提供了`bitflags!`宏
@@ -76,6 +78,144 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// A valid PTE with any of R/W/X set is a leaf (maps memory directly)
+    /// rather than pointing at the next-level page table - true for an
+    /// ordinary 4 KiB leaf as well as a 2 MiB/1 GiB huge page.
+    pub fn is_leaf(&self) -> bool {
+        self.readable() || self.writable() || self.executable()
+    }
+}
+
+// NOTE: `satp`的ASID字段宽度取决于具体的hart实现（SV39规范允许实现只接
+// 某个子集甚至完全不接），不能在编译期假定它总是16位。按标准探测手法：往
+// ASID字段整体写1，回读看硬件真正接住了多少位，再把`satp`原样写回去——这一步
+// 必须在还没有任何用户地址空间处于活跃状态时做一次（`ASID_ALLOCATOR`第一次
+// 被用到，也就是内核自己的根页表还在`satp`里的时候），不然会把正在运行的地址
+// 空间的根页表物理页号也顺手改写掉。
+/// Probe how many ASID bits this hart's `satp` actually implements by
+/// writing all 1s into the field and reading back what stuck. `0` means the
+/// hart has no hardware ASID support at all.
+fn hart_max_asid() -> usize {
+    use riscv::register::satp;
+    let prev = satp::read().bits();
+    unsafe {
+        satp::write(prev | (0xffffusize << 44));
+    }
+    let probed = (satp::read().bits() >> 44) & 0xffff;
+    unsafe {
+        satp::write(prev);
+    }
+    probed
+}
+
+// NOTE: 复用`StackFrameAllocator`那种"栈式回收"的分配策略，但单独给ASID建一个
+// 分配器而不是共享通用类型——这棵树里`PID`/内核栈号用的`RecycleAllocator`
+// 属于`task`层，`mm`层不应该反过来依赖它，所以这里照着同样的思路单独写一份。
+struct AsidAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+    /// Highest ASID this hart's `satp` can actually hold; `0` means the hart
+    /// has no ASID field at all.
+    max_asid: usize,
+}
+
+impl AsidAllocator {
+    fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+            max_asid: hart_max_asid(),
+        }
+    }
+
+    /// Whether this hart has no usable ASID field, in which case every
+    /// address space shares ASID `0` and every flush must behave like the
+    /// pre-ASID global `sfence.vma` this tree used to issue on every
+    /// unmap/remap.
+    fn degraded(&self) -> bool {
+        self.max_asid == 0
+    }
+
+    fn alloc(&mut self) -> usize {
+        if self.degraded() {
+            // No hardware ASID support (e.g. some k210 configs): keep handing
+            // out 0 forever, relying on flush_tlb_entry's degraded path
+            // (below) to fall back to a real global flush on every change.
+            return 0;
+        }
+        if let Some(asid) = self.recycled.pop() {
+            return asid;
+        }
+        if self.current > self.max_asid {
+            // NOTE: 和tornado-os的`asid_alloc`一样，ASID耗尽时的退路很粗糙：
+            // 直接把分配器清零重新从0开始发号，同时做一次真正的全局
+            // `sfence.vma`，让所有旧ASID在TLB里残留的条目一起失效。这意味着
+            // 如果同时存活的地址空间数量超过了`max_asid`，新旧地址空间可能会
+            // 撞上同一个ASID号——这是教学实现里刻意接受的简化，真要在生产环境
+            // 用就得换成引用计数/伙伴式的回收策略，保证同一时刻分配出去的
+            // ASID互不相同。
+            unsafe {
+                riscv::asm::sfence_vma_all();
+            }
+            self.current = 0;
+            self.recycled.clear();
+        }
+        self.current += 1;
+        self.current - 1
+    }
+
+    fn dealloc(&mut self, asid: usize) {
+        if self.degraded() {
+            // asid 0 is shared by every address space in degraded mode;
+            // there's nothing to return to the pool.
+            return;
+        }
+        assert!(asid < self.current);
+        assert!(
+            !self.recycled.iter().any(|&a| a == asid),
+            "asid {} has been deallocated!",
+            asid
+        );
+        self.recycled.push(asid);
+    }
+}
+
+lazy_static! {
+    static ref ASID_ALLOCATOR: UPSafeCell<AsidAllocator> =
+        unsafe { UPSafeCell::new(AsidAllocator::new()) };
+}
+
+/// RAII handle for an allocated ASID; the id is returned to the allocator
+/// once the owning [`PageTable`] is dropped
+struct AsidHandle(usize);
+
+impl Drop for AsidHandle {
+    fn drop(&mut self) {
+        ASID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+fn asid_alloc() -> AsidHandle {
+    AsidHandle(ASID_ALLOCATOR.exclusive_access().alloc())
+}
+
+/// Flush the TLB entry for a single `(asid, vpn)` pair, instead of the
+/// global `sfence.vma` a bare address-only flush would trigger.
+///
+/// Degrades to a real global flush if this hart has no hardware ASID
+/// support, since every address space shares `asid = 0` in that case and a
+/// scoped flush could otherwise leave another process's stale mapping for
+/// the same `vpn` behind.
+pub fn flush_tlb_entry(asid: usize, vpn: VirtPageNum) {
+    if ASID_ALLOCATOR.exclusive_access().degraded() {
+        unsafe {
+            riscv::asm::sfence_vma_all();
+        }
+        return;
+    }
+    unsafe {
+        riscv::asm::sfence_vma(asid, VirtAddr::from(vpn).0);
+    }
 }
 
 // NOTE: SV39 多级页表是以节点为单位进行管理的。
@@ -87,6 +227,9 @@ pub struct PageTable {
     // NOTE: 将FrameTracker进一步绑定到所在的物理页帧
     // 生命周期结束后，frames里的FrameTracker就被回收
     frames: Vec<FrameTracker>,
+    // NOTE: 只有`new()`创建的、真正代表一个地址空间的页表才持有`AsidHandle`；
+    // `from_token`临时借用别的地址空间查表用，不拥有、也不该在`Drop`时回收ASID
+    asid: Option<AsidHandle>,
 }
 
 // NOTE: 当遇到需要查一个特定页表（非当前正处在的地址空间的页表时），
@@ -100,6 +243,7 @@ impl PageTable {
         PageTable {
             root_ppn: frame.ppn, // NOTE: 一个页表只会有一个root节点
             frames: vec![frame],
+            asid: Some(asid_alloc()),
         }
     }
     // NOTE: 临时创建一个专门用于手动查表的PageTable，
@@ -110,12 +254,31 @@ impl PageTable {
         Self {
             root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
             frames: Vec::new(),
+            asid: None,
         }
     }
+    /// The ASID this page table was assigned, or `0` for a borrowed
+    /// [`PageTable::from_token`] instance (which never issues `sfence.vma`)
+    pub fn asid(&self) -> usize {
+        self.asid.as_ref().map_or(0, |handle| handle.0)
+    }
     // NOTE: 多级页表找到一个虚拟页号对应的页表项的可变引用
     // 如果在遍历的过程中发现有节点尚未创建则会新建一个节点
     /// Find PageTableEntry by VirtPageNum, create a frame for a 4KB page table if not exist
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_create_at_level(vpn, 2)
+    }
+    // NOTE: `level`与循环变量`i`同义：0是根节点那一级（覆盖1GiB），1是中间那一级
+    // （覆盖2MiB），2是默认的叶子级（4KiB）。`map_huge`靠提前在`i == level`的地方
+    // 截停，把本该继续往下走的节点当成大页的叶子页表项来写。
+    /// Find PageTableEntry by VirtPageNum, stopping (and creating the leaf
+    /// frame) at `level` instead of always descending to level 2. `level ==
+    /// 2` is the ordinary 4 KiB behavior `find_pte_create` uses.
+    fn find_pte_create_at_level(
+        &mut self,
+        vpn: VirtPageNum,
+        level: usize,
+    ) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         // NOTE: ppn表示当前节点的物理页号，最开始是多级页表的根节点
         let mut ppn = self.root_ppn;
@@ -124,7 +287,7 @@ impl PageTable {
             // NOTE: 通过get_pte_array取出当前节点的页表项数组，
             // 根据当前级页索引找到对应的页表项
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == level {
                 // NOTE: 如果当前节点为叶节点，返回
                 result = Some(pte);
                 break;
@@ -139,24 +302,33 @@ impl PageTable {
         }
         result
     }
-    // NOTE: 与find_pte_create不同是，不存在的时候直接返回None
-    /// Find PageTableEntry by VirtPageNum
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    // NOTE: 与find_pte_create不同是，不存在的时候直接返回None；
+    // 还要在没走到第2级之前，一旦遇到R/W/X任一标志位已经置位的页表项，
+    // 就判定这是一个大页叶子，提前返回（不然会把大页的物理页号错当成
+    // 下一级页表的物理页号继续往下查）
+    /// Find PageTableEntry by VirtPageNum, also returning the level (0/1/2)
+    /// the leaf was found at so huge pages are handled transparently
+    fn find_pte_with_level(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
             if i == 2 {
-                result = Some(pte);
-                break;
+                return Some((pte, i));
             }
             if !pte.is_valid() {
                 return None;
             }
+            if pte.is_leaf() {
+                return Some((pte, i));
+            }
             ppn = pte.ppn();
         }
-        result
+        unreachable!()
+    }
+    /// Find PageTableEntry by VirtPageNum
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_with_level(vpn).map(|(pte, _)| pte)
     }
     // NOTE: 找到或创建
     /// set the map between virtual page number and physical page number
@@ -165,6 +337,43 @@ impl PageTable {
         let pte = self.find_pte_create(vpn).unwrap();
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        // NOTE: 按ASID精确地只刷新这一个`vpn`的TLB项，而不是像以前那样依赖
+        // 调用方在外面手动`sfence.vma x0, <addr>`（隐含着ASID=0，相当于全刷）
+        flush_tlb_entry(self.asid(), vpn);
+    }
+    // NOTE: `level`的含义同`find_pte_create_at_level`：0是1GiB的`gigapage`，
+    // 1是2MiB的`megapage`，2就退化成普通的`map`。VPN/PPN必须在对应级别的
+    // 边界上对齐——否则大页覆盖的地址范围和物理页号的低位就对不上。
+    // `MemorySet`本该在这上面包一层（对齐够就走大页，不够就退回4KiB），
+    // 但`mm/memory_set.rs`在这棵树里缺失，没法从这里把它接上去。
+    /// Map `vpn` to `ppn` as a huge-page leaf at `level` (`1` = 2 MiB
+    /// megapage, `0` = 1 GiB gigapage; `level == 2` just calls [`Self::map`]).
+    /// Panics if `vpn`/`ppn` aren't aligned to what `level` covers.
+    #[allow(unused)]
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags, level: usize) {
+        if level == 2 {
+            self.map(vpn, ppn, flags);
+            return;
+        }
+        let align = 1usize << (9 * (2 - level));
+        assert_eq!(
+            vpn.0 % align,
+            0,
+            "vpn {:?} isn't aligned for a level-{} huge page",
+            vpn,
+            level
+        );
+        assert_eq!(
+            ppn.0 % align,
+            0,
+            "ppn {:?} isn't aligned for a level-{} huge page",
+            ppn,
+            level
+        );
+        let pte = self.find_pte_create_at_level(vpn, level).unwrap();
+        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        flush_tlb_entry(self.asid(), vpn);
     }
     // NOTE:
     /// remove the map between virtual page number and physical page number
@@ -173,17 +382,29 @@ impl PageTable {
         let pte = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
+        flush_tlb_entry(self.asid(), vpn);
     }
-    // NOTE: 调用find_pte实现，能够找到就返回一个拷贝，找不到就None
+    // NOTE: 调用find_pte_with_level实现，如果叶子是大页（level < 2），
+    // 还要把存储的物理页号和vpn里属于"页内偏移"那部分的索引位拼起来，
+    // 组成真正覆盖这个vpn的物理页号，对调用方（比如translated_byte_buffer）
+    // 完全透明——它们拿到的`PageTableEntry`看起来总是跟一个4KiB页对应。
     /// get the page table entry from the virtual page number
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        let (pte, level) = self.find_pte_with_level(vpn)?;
+        if level == 2 {
+            return Some(*pte);
+        }
+        let shift = 9 * (2 - level);
+        let offset_mask = (1usize << shift) - 1;
+        let full_ppn: PhysPageNum = (pte.ppn().0 | (vpn.0 & offset_mask)).into();
+        Some(PageTableEntry::new(full_ppn, pte.flags()))
     }
     // NOTE: 会按照satp CSR格式要求构造一个无符号64位无符号整数，
-    // 使得其分页模式为SV39，且将当前多级页表的根节点所在的物理页号填充进去。
+    // 使得其分页模式为SV39，ASID填入[59:44]，且将当前多级页表的根节点所在的
+    // 物理页号填充进去。
     /// get the token from the page table
     pub fn token(&self) -> usize {
-        8usize << 60 | self.root_ppn.0
+        8usize << 60 | self.asid() << 44 | self.root_ppn.0
     }
 }
 
@@ -215,3 +436,87 @@ pub fn translated_byte_buffer(
     }
     v
 }
+
+// NOTE: `translated_byte_buffer`对每个`vpn`都直接`.unwrap()`，遇到用户态传进来的
+// 野指针或者越权访问（比如把一个只读页传给需要写的syscall）就会让内核直接panic。
+// 这个版本把同样的逐页遍历改成逐页检查：不存在/无效/缺`U`标志/访问方向对不上
+// 请求的`R`/`W`标志，第一次出现就返回`Err(())`，交由调用方转成一个`-1`/`-EFAULT`
+// 返回给出错的用户进程，而不是拖垮整个内核。
+/// Like [`translated_byte_buffer`], but checks every page along the way
+/// (present, valid, carries `U`, and has the permission `write` requires)
+/// instead of panicking on the first bad one.
+pub fn translated_byte_buffer_checked(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    write: bool,
+) -> Result<Vec<&'static mut [u8]>, ()> {
+    let page_table = PageTable::from_token(token);
+    let mut start = ptr as usize;
+    let end = start + len;
+    let mut v = Vec::new();
+    while start < end {
+        let start_va = VirtAddr::from(start);
+        let mut vpn = start_va.floor();
+        let pte = page_table.translate(vpn).ok_or(())?;
+        if !pte.is_valid() || !pte.flags().contains(PTEFlags::U) {
+            return Err(());
+        }
+        if write && !pte.writable() {
+            return Err(());
+        }
+        if !write && !pte.readable() {
+            return Err(());
+        }
+        let ppn = pte.ppn();
+        vpn.step();
+        let mut end_va: VirtAddr = vpn.into();
+        end_va = end_va.min(VirtAddr::from(end));
+        if end_va.page_offset() == 0 {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+        } else {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+        }
+        start = end_va.into();
+    }
+    Ok(v)
+}
+
+/// Copy `*src` into user space at `dst`, returning `Err(())` instead of
+/// panicking if `dst` doesn't point at `size_of::<T>()` valid, writable
+/// user-mode bytes.
+pub fn copy_to_user<T: Copy>(token: usize, dst: *mut T, src: &T) -> Result<(), ()> {
+    let len = core::mem::size_of::<T>();
+    let dst_vec = translated_byte_buffer_checked(token, dst as *const u8, len, true)?;
+    let src_ptr = src as *const T as *const u8;
+    let mut offset = 0;
+    for chunk in dst_vec {
+        let chunk_len = chunk.len();
+        unsafe {
+            chunk.copy_from_slice(core::slice::from_raw_parts(
+                src_ptr.wrapping_add(offset),
+                chunk_len,
+            ));
+        }
+        offset += chunk_len;
+    }
+    Ok(())
+}
+
+/// Copy `size_of::<T>()` bytes from user space at `src` into `*dst`,
+/// returning `Err(())` instead of panicking if `src` doesn't point at
+/// valid, readable user-mode bytes.
+pub fn copy_from_user<T: Copy>(token: usize, src: *const T, dst: &mut T) -> Result<(), ()> {
+    let len = core::mem::size_of::<T>();
+    let src_vec = translated_byte_buffer_checked(token, src as *const u8, len, false)?;
+    let dst_ptr = dst as *mut T as *mut u8;
+    let mut offset = 0;
+    for chunk in src_vec {
+        let chunk_len = chunk.len();
+        unsafe {
+            core::ptr::copy_nonoverlapping(chunk.as_ptr(), dst_ptr.wrapping_add(offset), chunk_len);
+        }
+        offset += chunk_len;
+    }
+    Ok(())
+}
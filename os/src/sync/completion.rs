@@ -0,0 +1,75 @@
+//! One-shot completion ("latch") synchronization object
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// One-shot completion: unlike [`Condvar`](super::Condvar), it remembers
+/// that it fired, so `wait` called after the fact returns immediately
+/// instead of blocking forever, and it needs no companion mutex since it
+/// carries its own "done" state.
+pub struct Completion {
+    inner: UPSafeCell<CompletionInner>,
+}
+
+struct CompletionInner {
+    /// Set permanently by `complete_all`; once set every `wait` returns
+    /// immediately and `wait_queue` is never touched again
+    done: bool,
+    /// Number of `complete`/`complete_all` calls that have occurred
+    done_count: usize,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Completion {
+    /// Create a new, not-yet-completed completion
+    pub fn new() -> Self {
+        trace!("kernel: Completion::new");
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(CompletionInner {
+                    done: false,
+                    done_count: 0,
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// Block the current task until the completion fires, or return
+    /// immediately if it already has
+    pub fn wait(&self) {
+        trace!("kernel: Completion::wait");
+        let mut inner = self.inner.exclusive_access();
+        if inner.done {
+            return;
+        }
+        inner.wait_queue.push_back(current_task().unwrap());
+        drop(inner);
+        block_current_and_run_next();
+    }
+
+    /// Wake exactly one waiter and record that a completion happened,
+    /// without marking the completion permanently done - a later `wait`
+    /// still blocks unless `complete_all` is eventually called
+    pub fn complete(&self) {
+        trace!("kernel: Completion::complete");
+        let mut inner = self.inner.exclusive_access();
+        inner.done_count += 1;
+        if let Some(task) = inner.wait_queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+
+    /// Permanently mark the completion done and wake every current waiter;
+    /// every future `wait` returns immediately from here on
+    pub fn complete_all(&self) {
+        trace!("kernel: Completion::complete_all");
+        let mut inner = self.inner.exclusive_access();
+        inner.done = true;
+        inner.done_count += 1;
+        while let Some(task) = inner.wait_queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+}
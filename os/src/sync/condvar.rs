@@ -0,0 +1,50 @@
+//! Condition variable
+
+use super::{Mutex, UPSafeCell};
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// Condition variable, used together with a [`Mutex`]
+pub struct Condvar {
+    inner: UPSafeCell<CondvarInner>,
+}
+
+struct CondvarInner {
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Condvar {
+    /// Create a new, empty condition variable
+    pub fn new() -> Self {
+        trace!("kernel: Condvar::new");
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(CondvarInner {
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// Wake the longest-waiting thread blocked on this condvar, if any
+    pub fn signal(&self) {
+        trace!("kernel: Condvar::signal");
+        let mut inner = self.inner.exclusive_access();
+        if let Some(task) = inner.wait_queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+
+    /// Release `mutex`, block the current thread on this condvar, and
+    /// re-acquire `mutex` before returning, as the usual condition-variable
+    /// contract requires
+    pub fn wait(&self, mutex: Arc<dyn Mutex>) {
+        trace!("kernel: Condvar::wait");
+        mutex.unlock();
+        let mut inner = self.inner.exclusive_access();
+        inner.wait_queue.push_back(current_task().unwrap());
+        drop(inner);
+        block_current_and_run_next();
+        mutex.lock();
+    }
+}
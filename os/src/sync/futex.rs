@@ -0,0 +1,107 @@
+//! Fast userspace mutex (futex) subsystem.
+//!
+//! Unlike `Mutex`/`Semaphore`, which are identified by a small integer id
+//! pre-registered in a per-process list, a futex is identified by the
+//! physical address `uaddr` currently translates to - so userspace can build
+//! a lock out of a plain word in memory and only traps into the kernel once
+//! it actually contends, instead of calling `sys_mutex_create` up front.
+
+use super::UPSafeCell;
+use crate::config::PAGE_SIZE;
+use crate::mm::{translated_ref, PageTable, VirtAddr};
+use crate::task::{
+    block_current_and_run_next, current_task, current_user_token, wakeup_task, TaskControlBlock,
+};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+
+/// `sys_futex` operation: block unless `*uaddr != val`
+pub const FUTEX_WAIT: usize = 0;
+/// `sys_futex` operation: wake up to `val` waiters matching `bitset`
+pub const FUTEX_WAKE: usize = 1;
+
+/// Bitset value meaning "match every waiter", for a plain wake that doesn't
+/// care which wait-side bitset was used
+pub const FUTEX_BITSET_MATCH_ANY: u32 = !0;
+
+/// A task parked on a futex, along with the bitset it was waiting for
+struct FutexWaiter {
+    task: Arc<TaskControlBlock>,
+    bitset: u32,
+}
+
+/// All currently-contended futexes, keyed by the physical address `uaddr`
+/// resolved to. Buckets are created lazily on first wait and dropped once
+/// empty so an unbounded number of distinct `uaddr`s never leaves stale
+/// entries behind.
+struct FutexTable {
+    buckets: UPSafeCell<BTreeMap<usize, VecDeque<FutexWaiter>>>,
+}
+
+lazy_static! {
+    static ref FUTEX_TABLE: FutexTable = FutexTable {
+        buckets: unsafe { UPSafeCell::new(BTreeMap::new()) },
+    };
+}
+
+/// Resolve `uaddr`, a pointer in the current task's address space, to the
+/// physical address used as the bucket key - so two threads (even across
+/// address spaces, were that ever supported) that map the same page agree on
+/// which bucket a given word belongs to.
+fn futex_key(uaddr: *const u32) -> usize {
+    let token = current_user_token();
+    let va = VirtAddr::from(uaddr as usize);
+    let ppn = PageTable::from_token(token)
+        .translate(va.floor())
+        .unwrap()
+        .ppn();
+    ppn.0 * PAGE_SIZE + va.page_offset()
+}
+
+/// `FUTEX_WAIT`: if `*uaddr == val`, block the current task on `uaddr`'s
+/// bucket carrying `bitset`; otherwise return immediately. The comparison
+/// and the enqueue happen with the bucket's `UPSafeCell` held, so a
+/// concurrent `FUTEX_WAKE` can't land between "we checked" and "we queued
+/// ourselves" and be lost.
+pub fn futex_wait(uaddr: *const u32, val: u32, bitset: u32) {
+    trace!("kernel: futex_wait");
+    let key = futex_key(uaddr);
+    let mut buckets = FUTEX_TABLE.buckets.exclusive_access();
+    if *translated_ref(current_user_token(), uaddr) != val {
+        return;
+    }
+    let bucket = buckets.entry(key).or_insert_with(VecDeque::new);
+    bucket.push_back(FutexWaiter {
+        task: current_task().unwrap(),
+        bitset,
+    });
+    drop(buckets);
+    block_current_and_run_next();
+}
+
+/// `FUTEX_WAKE`: wake up to `max_waiters` tasks parked on `uaddr`'s bucket
+/// whose stored bitset intersects `bitset` (use [`FUTEX_BITSET_MATCH_ANY`]
+/// for a plain wake), in FIFO order. Returns the number actually woken.
+pub fn futex_wake(uaddr: *const u32, max_waiters: u32, bitset: u32) -> u32 {
+    trace!("kernel: futex_wake");
+    let key = futex_key(uaddr);
+    let mut buckets = FUTEX_TABLE.buckets.exclusive_access();
+    let Some(mut bucket) = buckets.remove(&key) else {
+        return 0;
+    };
+    let mut woken = 0;
+    let mut remaining = VecDeque::new();
+    while let Some(waiter) = bucket.pop_front() {
+        if woken < max_waiters && waiter.bitset & bitset != 0 {
+            wakeup_task(waiter.task);
+            woken += 1;
+        } else {
+            remaining.push_back(waiter);
+        }
+    }
+    if !remaining.is_empty() {
+        buckets.insert(key, remaining);
+    }
+    woken
+}
@@ -1,17 +1,27 @@
 //! Mutex (spin-like and blocking(sleep))
 
 use super::UPSafeCell;
-use crate::task::{block_current_and_run_next, suspend_current_and_run_next};
-use crate::task::{current_process, TaskControlBlock};
-use crate::task::{current_task, wakeup_task};
+use crate::task::{
+    block_current_and_run_next, current_task, suspend_current_and_run_next, wakeup_task,
+    TaskControlBlock, TaskStatus,
+};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
 use alloc::{collections::VecDeque, sync::Arc};
 
+// NOTE: `Mutex::lock`/`unlock`不再接收`tid`/`mutex_id`，也不再自己维护
+// `allocation`/`need`——死锁检测需要的那份记账现在完全是调用方（目前是
+// `syscall::sync::sys_mutex_lock`）的事：它在真正调用`lock`之前就already
+// 知道会不会造成死锁，`Mutex`本身只负责“拿到/拿不到就睡”这件事。
 /// Mutex trait
 pub trait Mutex: Sync + Send {
     /// Lock the mutex
-    fn lock(&self, tid: usize, mutex_id: usize);
+    fn lock(&self);
     /// Unlock the mutex
     fn unlock(&self);
+    /// Like [`Mutex::lock`], but gives up and returns `false` if the lock is
+    /// still not held after `ms` milliseconds; returns `true` if it was
+    /// acquired (whether immediately or after waiting).
+    fn lock_timeout(&self, ms: usize) -> bool;
 }
 
 /// Spinlock Mutex struct
@@ -30,25 +40,15 @@ impl MutexSpin {
 
 impl Mutex for MutexSpin {
     /// Lock the spinlock mutex
-    fn lock(&self, tid: usize, mutex_id: usize) {
+    fn lock(&self) {
         trace!("kernel: MutexSpin::lock");
         loop {
             let mut locked = self.locked.exclusive_access();
             if *locked {
                 drop(locked);
-                if tid != 0xdead {
-                    current_process().inner_exclusive_access().need[0][tid][mutex_id] += 1;
-                }
                 suspend_current_and_run_next();
-                if tid != 0xdead {
-                    current_process().inner_exclusive_access().need[0][tid][mutex_id] -= 1;
-                }
                 continue;
             } else {
-                if tid != 0xdead {
-                    current_process().inner_exclusive_access().allocation[0][tid][mutex_id] += 1;
-                    current_process().inner_exclusive_access().available[0][mutex_id] -= 1;
-                }
                 *locked = true;
                 return;
             }
@@ -60,6 +60,25 @@ impl Mutex for MutexSpin {
         let mut locked = self.locked.exclusive_access();
         *locked = false;
     }
+
+    fn lock_timeout(&self, ms: usize) -> bool {
+        trace!("kernel: MutexSpin::lock_timeout");
+        let deadline = get_time_ms() + ms;
+        loop {
+            let mut locked = self.locked.exclusive_access();
+            if *locked {
+                if get_time_ms() >= deadline {
+                    return false;
+                }
+                drop(locked);
+                suspend_current_and_run_next();
+                continue;
+            } else {
+                *locked = true;
+                return true;
+            }
+        }
+    }
 }
 
 /// Blocking Mutex struct
@@ -89,7 +108,7 @@ impl MutexBlocking {
 
 impl Mutex for MutexBlocking {
     /// Lock the blocking `mutex`
-    fn lock(&self, tid: usize, mutex_id: usize) {
+    fn lock(&self) {
         trace!("kernel: MutexBlocking::lock");
         let mut mutex_inner = self.inner.exclusive_access();
         // 如果互斥锁`mutex`已经被其他线程获取了
@@ -97,22 +116,12 @@ impl Mutex for MutexBlocking {
             // 那么将当前线程放入等待队列中
             mutex_inner.wait_queue.push_back(current_task().unwrap());
             drop(mutex_inner);
-            if tid != 0xdead {
-                current_process().inner_exclusive_access().need[0][tid][mutex_id] += 1;
-            }
             // 让当前线程处于等待状态，并调度其他线程执行
             block_current_and_run_next();
-            if tid != 0xdead {
-                current_process().inner_exclusive_access().need[0][tid][mutex_id] -= 1;
-            }
         } else {
             // 如果互斥锁`mutex`还没有被获取，那么当前线程会获取给互斥锁，并返回系统调用
             mutex_inner.locked = true;
         }
-        if tid != 0xdead {
-            current_process().inner_exclusive_access().allocation[0][tid][mutex_id] += 1;
-            current_process().inner_exclusive_access().available[0][mutex_id] -= 1;
-        }
     }
 
     /// Unlock the blocking `mutex`
@@ -121,11 +130,191 @@ impl Mutex for MutexBlocking {
         let mut mutex_inner = self.inner.exclusive_access();
         assert!(mutex_inner.locked);
         // 如果有等待的线程，唤醒等待最久的那个线程，相当于将锁的所有权移交给该线程。
-        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
-            wakeup_task(waking_task);
+        loop {
+            match mutex_inner.wait_queue.pop_front() {
+                Some(waking_task) => {
+                    // `check_timers` may have already fired for this waiter
+                    // (it was sitting in `wait_queue` *and* timed out before
+                    // we got here) and made it `Ready` already. Handing the
+                    // lock off to it anyway would strand it: it'll see
+                    // `timed_out` on its next run, return `false` from
+                    // `lock_timeout`, and never call `unlock`. Drop it and
+                    // keep looking instead.
+                    if waking_task.inner_exclusive_access().task_status != TaskStatus::Blocked {
+                        continue;
+                    }
+                    // Cancel any pending `lock_timeout` timer for it now
+                    // that it's being handed the lock normally.
+                    remove_timer(waking_task.clone());
+                    wakeup_task(waking_task);
+                    return;
+                }
+                None => {
+                    // 如果没有等待线程，释放锁
+                    mutex_inner.locked = false;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Like [`Mutex::lock`], but also registers a timer for `ms`
+    /// milliseconds from now. Returns `true` if the lock was actually
+    /// acquired, `false` if the timeout fired first - in which case the
+    /// caller does not hold the lock.
+    fn lock_timeout(&self, ms: usize) -> bool {
+        trace!("kernel: MutexBlocking::lock_timeout");
+        let mut mutex_inner = self.inner.exclusive_access();
+        if !mutex_inner.locked {
+            mutex_inner.locked = true;
+            return true;
+        }
+        let task = current_task().unwrap();
+        task.inner_exclusive_access().timed_out = false;
+        mutex_inner.wait_queue.push_back(task.clone());
+        drop(mutex_inner);
+        add_timer(get_time_ms() + ms, task.clone());
+        block_current_and_run_next();
+        // We're running again: either `unlock` handed the lock to us
+        // (having already popped us from `wait_queue` and cancelled our
+        // timer), or the timer fired first.
+        if task.inner_exclusive_access().timed_out {
+            // The timer beat `unlock` to it - we may still be sitting in
+            // `wait_queue`, so remove ourselves before reporting failure.
+            let mut mutex_inner = self.inner.exclusive_access();
+            mutex_inner.wait_queue.retain(|t| !Arc::ptr_eq(t, &task));
+            false
+        } else {
+            true
+        }
+    }
+}
+
+/// Blocking mutex with priority inheritance.
+///
+/// Plain `MutexBlocking` can suffer priority inversion: a low-priority
+/// holder blocks a high-priority waiter, but unrelated medium-priority
+/// tasks keep preempting the holder since nothing marks it as urgent. This
+/// variant tracks its owner and temporarily raises the owner's
+/// stride-scheduling priority to the max of its own and every queued
+/// waiter's whenever the wait queue changes, restoring the owner's
+/// priority as soon as it stops being the owner.
+pub struct MutexBlockingPi {
+    inner: UPSafeCell<MutexBlockingPiInner>,
+}
+
+struct MutexBlockingPiInner {
+    /// Current holder, and the priority it had before any inheritance was
+    /// applied to it (so `unlock` knows what to restore)
+    owner: Option<(Arc<TaskControlBlock>, u64)>,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl MutexBlockingPiInner {
+    /// Raise the owner's priority to the max of its base priority and every
+    /// queued waiter's current priority
+    fn inherit_priority(&self) {
+        let Some((owner, base_priority)) = &self.owner else {
+            return;
+        };
+        let boosted = self
+            .wait_queue
+            .iter()
+            .map(|task| task.inner_exclusive_access().priority)
+            .fold(*base_priority, u64::max);
+        owner.set_priority(boosted);
+    }
+}
+
+impl MutexBlockingPi {
+    /// Create a new priority-inheriting blocking mutex
+    pub fn new() -> Self {
+        trace!("kernel: MutexBlockingPi::new");
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(MutexBlockingPiInner {
+                    owner: None,
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+}
+
+impl Mutex for MutexBlockingPi {
+    fn lock(&self) {
+        trace!("kernel: MutexBlockingPi::lock");
+        let mut mutex_inner = self.inner.exclusive_access();
+        if mutex_inner.owner.is_some() {
+            mutex_inner.wait_queue.push_back(current_task().unwrap());
+            mutex_inner.inherit_priority();
+            drop(mutex_inner);
+            block_current_and_run_next();
+        } else {
+            let current = current_task().unwrap();
+            let base_priority = current.inner_exclusive_access().priority;
+            mutex_inner.owner = Some((current, base_priority));
+        }
+    }
+
+    fn unlock(&self) {
+        trace!("kernel: MutexBlockingPi::unlock");
+        let mut mutex_inner = self.inner.exclusive_access();
+        let (owner, base_priority) = mutex_inner
+            .owner
+            .take()
+            .expect("unlock of unlocked MutexBlockingPi");
+        owner.set_priority(base_priority);
+        loop {
+            match mutex_inner.wait_queue.pop_front() {
+                Some(next) => {
+                    // See `MutexBlocking::unlock`: a waiter the timer already
+                    // fired for must not be handed the lock - it would see
+                    // `timed_out` on its next run and never call `unlock`.
+                    if next.inner_exclusive_access().task_status != TaskStatus::Blocked {
+                        continue;
+                    }
+                    let next_base_priority = next.inner_exclusive_access().priority;
+                    mutex_inner.owner = Some((next.clone(), next_base_priority));
+                    mutex_inner.inherit_priority();
+                    drop(mutex_inner);
+                    // Cancel any pending `lock_timeout` timer for `next` now
+                    // that it's being handed the lock normally.
+                    remove_timer(next.clone());
+                    wakeup_task(next);
+                    return;
+                }
+                None => return,
+            }
+        }
+    }
+
+    fn lock_timeout(&self, ms: usize) -> bool {
+        trace!("kernel: MutexBlockingPi::lock_timeout");
+        let mut mutex_inner = self.inner.exclusive_access();
+        if mutex_inner.owner.is_none() {
+            let current = current_task().unwrap();
+            let base_priority = current.inner_exclusive_access().priority;
+            mutex_inner.owner = Some((current, base_priority));
+            return true;
+        }
+        let task = current_task().unwrap();
+        task.inner_exclusive_access().timed_out = false;
+        mutex_inner.wait_queue.push_back(task.clone());
+        mutex_inner.inherit_priority();
+        drop(mutex_inner);
+        add_timer(get_time_ms() + ms, task.clone());
+        block_current_and_run_next();
+        if task.inner_exclusive_access().timed_out {
+            // The timer beat `unlock` to it - we may still be sitting in
+            // `wait_queue`, so remove ourselves and let the next waiter's
+            // (or nobody's) priority boost reflect that.
+            let mut mutex_inner = self.inner.exclusive_access();
+            mutex_inner.wait_queue.retain(|t| !Arc::ptr_eq(t, &task));
+            mutex_inner.inherit_priority();
+            false
         } else {
-            // 如果没有等待线程，释放锁
-            mutex_inner.locked = false;
+            true
         }
     }
 }
@@ -0,0 +1,94 @@
+//! Reader-writer lock, writer-preference
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+
+/// Reader-writer lock: any number of readers may hold it concurrently, but a
+/// writer needs exclusive access.
+///
+/// Writer-preference: a read lock blocks not only while a writer is active,
+/// but also while any writer is already waiting, so a steady stream of
+/// readers can't starve a writer out indefinitely. A write lock blocks
+/// until there are no active readers and no active writer.
+pub struct RwLock {
+    inner: UPSafeCell<RwLockInner>,
+}
+
+struct RwLockInner {
+    readers: usize,
+    writer_active: bool,
+    reader_queue: VecDeque<Arc<TaskControlBlock>>,
+    writer_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RwLock {
+    /// Create a new, unlocked reader-writer lock
+    pub fn new() -> Self {
+        trace!("kernel: RwLock::new");
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(RwLockInner {
+                    readers: 0,
+                    writer_active: false,
+                    reader_queue: VecDeque::new(),
+                    writer_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// Acquire a read lock, blocking while a writer is active or waiting
+    pub fn read_lock(&self) {
+        trace!("kernel: RwLock::read_lock");
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer_active || !inner.writer_queue.is_empty() {
+            inner.reader_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        } else {
+            inner.readers += 1;
+        }
+    }
+
+    /// Acquire the write lock, blocking until no readers or writer are active
+    pub fn write_lock(&self) {
+        trace!("kernel: RwLock::write_lock");
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer_active || inner.readers > 0 {
+            inner.writer_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        } else {
+            inner.writer_active = true;
+        }
+    }
+
+    /// Release a read or write lock, waking the next writer if one is
+    /// waiting, otherwise every currently-waiting reader
+    pub fn unlock(&self) {
+        trace!("kernel: RwLock::unlock");
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer_active {
+            inner.writer_active = false;
+        } else {
+            assert!(inner.readers > 0, "unlock of unlocked RwLock");
+            inner.readers -= 1;
+            if inner.readers > 0 {
+                return;
+            }
+        }
+        if let Some(writer) = inner.writer_queue.pop_front() {
+            inner.writer_active = true;
+            drop(inner);
+            wakeup_task(writer);
+        } else {
+            let waiting_readers: Vec<_> = inner.reader_queue.drain(..).collect();
+            inner.readers += waiting_readers.len();
+            drop(inner);
+            for reader in waiting_readers {
+                wakeup_task(reader);
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Counting semaphore (blocking)
+
+use super::UPSafeCell;
+use crate::task::{
+    block_current_and_run_next, current_task, wakeup_task, TaskControlBlock, TaskStatus,
+};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// Counting semaphore, blocking variant
+pub struct Semaphore {
+    inner: UPSafeCell<SemaphoreInner>,
+}
+
+struct SemaphoreInner {
+    count: isize,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Semaphore {
+    /// Create a semaphore with `res_count` instances initially available
+    pub fn new(res_count: usize) -> Self {
+        trace!("kernel: Semaphore::new");
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(SemaphoreInner {
+                    count: res_count as isize,
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// `V` operation: release one instance, waking the longest-waiting
+    /// blocked thread if `count` was negative (i.e. someone was waiting)
+    pub fn up(&self) {
+        trace!("kernel: Semaphore::up");
+        let mut inner = self.inner.exclusive_access();
+        inner.count += 1;
+        while inner.count <= 0 {
+            let Some(task) = inner.wait_queue.pop_front() else {
+                break;
+            };
+            // The timer may have already fired for this waiter (it was
+            // sitting in `wait_queue` *and* timed out before we got here).
+            // It will restore `count` itself when `down_timeout`'s timeout
+            // branch runs, so don't hand it this instance too - drop it
+            // and keep looking instead.
+            if task.inner_exclusive_access().task_status != TaskStatus::Blocked {
+                continue;
+            }
+            // Cancel any pending `down_timeout` timer for it now that it's
+            // being handed an instance normally. No-op if it never
+            // registered one.
+            remove_timer(task.clone());
+            wakeup_task(task);
+            break;
+        }
+    }
+
+    /// `P` operation: acquire one instance, blocking the current thread if
+    /// none is immediately available
+    pub fn down(&self) {
+        trace!("kernel: Semaphore::down");
+        let mut inner = self.inner.exclusive_access();
+        inner.count -= 1;
+        if inner.count < 0 {
+            inner.wait_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    /// Like [`Semaphore::down`], but also registers a timer for `ms`
+    /// milliseconds from now. Returns `true` if an instance was actually
+    /// acquired, `false` if the timeout fired first - in which case the
+    /// count is restored and the caller holds nothing.
+    pub fn down_timeout(&self, ms: usize) -> bool {
+        trace!("kernel: Semaphore::down_timeout");
+        let mut inner = self.inner.exclusive_access();
+        inner.count -= 1;
+        if inner.count >= 0 {
+            return true;
+        }
+        let task = current_task().unwrap();
+        task.inner_exclusive_access().timed_out = false;
+        inner.wait_queue.push_back(task.clone());
+        drop(inner);
+        add_timer(get_time_ms() + ms, task.clone());
+        block_current_and_run_next();
+        if task.inner_exclusive_access().timed_out {
+            // The timer beat `up` to it - we may still be sitting in
+            // `wait_queue`, so remove ourselves and give the instance back.
+            let mut inner = self.inner.exclusive_access();
+            inner.wait_queue.retain(|t| !Arc::ptr_eq(t, &task));
+            inner.count += 1;
+            false
+        } else {
+            true
+        }
+    }
+}
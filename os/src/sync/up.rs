@@ -0,0 +1,34 @@
+//! Uniprocessor interior-mutability primitive.
+
+use core::cell::{RefCell, RefMut};
+
+/// Wrap a shared value so it can be mutated through a `&self` reference
+/// without `unsafe` at every call site.
+///
+/// Only sound on a single core: there is no real locking, just a `RefCell`'s
+/// borrow check panicking on reentrant access. Every kernel global that needs
+/// interior mutability (`TASK_MANAGER`, `PID2TASK`, `TIMERS`,
+/// each `TaskControlBlock`'s `inner`, ...) is wrapped in one of these.
+pub struct UPSafeCell<T> {
+    inner: RefCell<T>,
+}
+
+// NOTE: 单核上不会有真正的并发访问，这里手动声明`Sync`使得它可以被放进
+// `lazy_static!`的全局变量里；调用方必须自己保证“单核”这个前提成立。
+unsafe impl<T> Sync for UPSafeCell<T> {}
+
+impl<T> UPSafeCell<T> {
+    /// Wrap `value`. Caller must guarantee this runs on a single core only.
+    pub unsafe fn new(value: T) -> Self {
+        Self {
+            inner: RefCell::new(value),
+        }
+    }
+
+    /// Get exclusive access to the inner value. Panics if it is already
+    /// borrowed (e.g. a reentrant call while a previous guard is still
+    /// alive).
+    pub fn exclusive_access(&self) -> RefMut<'_, T> {
+        self.inner.borrow_mut()
+    }
+}
@@ -1,8 +1,13 @@
 //! `File and filesystem-related syscalls`
 
-use crate::fs::{open_file, OSInode, OpenFlags, Stat, StatMode, ROOT_INODE};
-use crate::mm::{translated_byte_buffer, translated_str, UserBuffer};
-use crate::task::{current_task, current_user_token};
+use crate::fs::{
+    make_pipe, mkdir, open_file, OSInode, OpenFlags, PollFlags, Stat, StatMode, ROOT_INODE,
+};
+use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
+use crate::task::{
+    block_current_and_run_next, current_task, current_user_token, TaskControlBlockInner,
+};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
 use core::any::Any;
 
 /// 让其更有普适性
@@ -78,6 +83,22 @@ pub fn sys_open(path: *const u8, flags: u32) -> isize {
     }
 }
 
+/// **功能**：在文件系统中创建一个目录。
+/// **参数**：`path`描述要创建的目录的路径，支持多级路径（以`/`分隔），
+/// 除最后一级之外的每一级都必须已经是一个目录。
+/// **返回值**：创建成功返回 0，否则返回 -1。可能的错误原因是：路径中间的某一级不存在
+/// 或不是目录，或者最后一级已经存在。
+pub fn sys_mkdir(path: *const u8) -> isize {
+    trace!("kernel:pid[{}] sys_mkdir", current_task().unwrap().pid.0);
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    if mkdir(path.as_str()) {
+        0
+    } else {
+        -1
+    }
+}
+
 pub fn sys_close(fd: usize) -> isize {
     trace!("kernel:pid[{}] sys_close", current_task().unwrap().pid.0);
     let task = current_task().unwrap();
@@ -111,6 +132,7 @@ pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
 
     let ino: u64;
     let nlink: u32;
+    let mode: StatMode;
 
     //    let mut ino = 0 as u64;
     //    let mut nlink = 0 as u32;
@@ -120,6 +142,11 @@ pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
         ino = os_node.get_inode_id();
         let (block_id, block_offset) = os_node.get_inode_pos();
         nlink = ROOT_INODE.get_link_num(block_id, block_offset);
+        mode = if os_node.is_dir() {
+            StatMode::DIR
+        } else {
+            StatMode::FILE
+        };
     } else {
         return -1;
     }
@@ -127,7 +154,7 @@ pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
     let stat = &Stat {
         dev: 0,
         ino: ino,
-        mode: StatMode::FILE,
+        mode,
         nlink: nlink,
         pad: [0; 7],
     };
@@ -182,3 +209,225 @@ pub fn sys_unlinkat(name: *const u8) -> isize {
     }
     -1
 }
+
+/// Selects what `offset` is relative to in `sys_lseek`, mirroring the
+/// `SeekFrom` used by other `VFS` implementations
+#[derive(Debug, Clone, Copy)]
+pub enum SeekFrom {
+    /// Seek to an absolute byte offset from the start of the file
+    Start(u64),
+    /// Seek relative to the current offset
+    Current(i64),
+    /// Seek relative to the end of the file
+    End(i64),
+}
+
+/// **功能**：重新定位一个已打开的常规文件的读写偏移量。
+/// **参数**：`fd`是待操作文件的文件描述符；`offset`是偏移量；`whence`指明偏移量的参照：
+/// `0`表示`SeekFrom::Start`，`1`表示`SeekFrom::Current`，`2`表示`SeekFrom::End`。
+/// **返回值**：如果出现了错误（`fd`不合法、不是常规文件、或计算出的偏移量为负）则返回 -1，
+/// 否则返回新的偏移量。
+/// `syscall ID`：62
+pub fn sys_lseek(fd: usize, offset: i64, whence: i32) -> isize {
+    trace!("kernel:pid[{}] sys_lseek", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    let file = match &inner.fd_table[fd] {
+        Some(file) => file.clone(),
+        None => return -1,
+    };
+    drop(inner);
+    let any: &dyn Any = file.as_any();
+    let os_inode = match any.downcast_ref::<OSInode>() {
+        Some(os_inode) => os_inode,
+        None => return -1,
+    };
+    let seek = match whence {
+        0 => SeekFrom::Start(offset as u64),
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => return -1,
+    };
+    let new_offset = match seek {
+        SeekFrom::Start(pos) => pos as i64,
+        SeekFrom::Current(delta) => os_inode.get_offset() as i64 + delta,
+        SeekFrom::End(delta) => os_inode.size() as i64 + delta,
+    };
+    if new_offset < 0 {
+        return -1;
+    }
+    os_inode.set_offset(new_offset as usize);
+    new_offset as isize
+}
+
+/// **功能**：将进程中一个已经打开的文件复制一份并分配到一个新的文件描述符中。
+/// **参数**：`fd`表示进程中一个已经打开的文件的文件描述符。
+/// **返回值**：如果出现了错误则返回 -1，否则能够访问已打开文件的新文件描述符。
+/// 可能的错误原因是：`fd`不是一个合法的文件描述符。
+/// `syscall ID`：24
+pub fn sys_dup(fd: usize) -> isize {
+    trace!("kernel:pid[{}] sys_dup", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return -1;
+    }
+    let file = inner.fd_table[fd].as_ref().unwrap().clone();
+    let new_fd = inner.alloc_fd();
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+
+/// **功能**：将进程中一个已经打开的文件复制一份并安装到指定的文件描述符`new_fd`上，
+/// 如果`new_fd`已经指向一个打开的文件，先将其关闭。
+/// **参数**：`old_fd`是待复制的文件描述符，`new_fd`是目标文件描述符。
+/// **返回值**：如果出现了错误则返回 -1，否则返回`new_fd`。
+/// 可能的错误原因是：`old_fd`不是一个合法的文件描述符。
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    trace!("kernel:pid[{}] sys_dup2", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if old_fd >= inner.fd_table.len() || inner.fd_table[old_fd].is_none() {
+        return -1;
+    }
+    let file = inner.fd_table[old_fd].as_ref().unwrap().clone();
+    while new_fd >= inner.fd_table.len() {
+        inner.fd_table.push(None);
+    }
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+
+/// **功能**：为当前进程打开一个管道。
+/// **参数**：`pipe`表示应用地址空间中的一个长度为`2`的`usize`数组的起始地址，
+/// 内核需要按顺序将管道读端和写端的文件描述符写入到数组中。
+/// **返回值**：如果出现了错误则返回 -1，否则返回 0。
+/// `syscall ID`：59
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    trace!("kernel:pid[{}] sys_pipe", current_task().unwrap().pid.0);
+    let task = current_task().unwrap();
+    let token = current_user_token();
+    let mut inner = task.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = inner.alloc_fd();
+    inner.fd_table[read_fd] = Some(pipe_read);
+    let write_fd = inner.alloc_fd();
+    inner.fd_table[write_fd] = Some(pipe_write);
+    *translated_refmut(token, pipe) = read_fd;
+    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
+    0
+}
+
+/// A readiness query passed to `sys_poll`, one per fd being watched
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PollFd {
+    /// file descriptor to watch
+    pub fd: i32,
+    /// readiness bits the caller cares about
+    pub events: PollFlags,
+    /// readiness bits actually observed, filled in by `sys_poll`
+    pub revents: PollFlags,
+}
+
+// NOTE: 每个fd是否需要登记等待、登记在哪个等待队列上，都是具体`File`实现
+// （目前只有`Pipe`真的有等待队列）自己的事；`sys_poll`只负责在“这一轮都没
+// 就绪”时调用每个fd的`unregister_waiter`清掉上一轮的登记、再统一挂起一次，
+// 被`timer`或任意一个被监视的`Pipe`唤醒后回来重新检查。
+/// Remove whatever wait-queue registration `poll_or_register` left behind
+/// on each watched fd, so a `sys_poll` call that blocked across several fds
+/// doesn't leave the task parked on the ones that never became ready.
+fn unregister_poll_waiters(
+    token: usize,
+    fds: *mut PollFd,
+    nfds: usize,
+    inner: &TaskControlBlockInner,
+) {
+    for i in 0..nfds {
+        let pollfd_ptr = unsafe { fds.add(i) };
+        let pollfd = *translated_refmut(token, pollfd_ptr);
+        if (pollfd.fd as usize) < inner.fd_table.len() {
+            if let Some(file) = &inner.fd_table[pollfd.fd as usize] {
+                file.unregister_waiter();
+            }
+        }
+    }
+}
+
+/// Poll `nfds` file descriptors for readiness. `timeout_ms < 0` waits
+/// forever, `timeout_ms == 0` returns immediately. Returns the number of
+/// fds with at least one requested event ready.
+///
+/// Blocks on the same wait-queue mechanism `Pipe::read`/`write` already use
+/// (via `File::poll_or_register`), woken either by whichever watched fd
+/// becomes ready or by a timer registered for `timeout_ms` - not by
+/// periodically re-polling off a busy loop.
+pub fn sys_poll(fds: *mut PollFd, nfds: usize, timeout_ms: isize) -> isize {
+    trace!("kernel:pid[{}] sys_poll", current_task().unwrap().pid.0);
+    let token = current_user_token();
+    let deadline = if timeout_ms < 0 {
+        None
+    } else {
+        Some(get_time_ms() + timeout_ms as usize)
+    };
+    let task = current_task().unwrap();
+    // `timeout_ms == 0` must return immediately without ever parking the
+    // current task anywhere.
+    let will_block = timeout_ms != 0;
+    let mut registered = false;
+    loop {
+        let inner = task.inner_exclusive_access();
+        if registered {
+            unregister_poll_waiters(token, fds, nfds, &inner);
+            registered = false;
+        }
+        let mut ready = 0isize;
+        for i in 0..nfds {
+            let pollfd_ptr = unsafe { fds.add(i) };
+            let mut pollfd = *translated_refmut(token, pollfd_ptr);
+            let revents = if (pollfd.fd as usize) < inner.fd_table.len() {
+                match &inner.fd_table[pollfd.fd as usize] {
+                    Some(file) => {
+                        let observed = if will_block {
+                            file.poll_or_register(pollfd.events)
+                        } else {
+                            file.poll()
+                        };
+                        observed & (pollfd.events | PollFlags::HUP)
+                    }
+                    None => PollFlags::empty(),
+                }
+            } else {
+                PollFlags::empty()
+            };
+            pollfd.revents = revents;
+            *translated_refmut(token, pollfd_ptr) = pollfd;
+            if !revents.is_empty() {
+                ready += 1;
+            }
+        }
+        if ready > 0 && will_block {
+            unregister_poll_waiters(token, fds, nfds, &inner);
+        }
+        drop(inner);
+        if ready > 0 || !will_block {
+            return ready;
+        }
+        registered = true;
+        if let Some(deadline) = deadline {
+            add_timer(deadline, task.clone());
+        }
+        block_current_and_run_next();
+        remove_timer(task.clone());
+        if let Some(deadline) = deadline {
+            if get_time_ms() >= deadline {
+                let inner = task.inner_exclusive_access();
+                unregister_poll_waiters(token, fds, nfds, &inner);
+                return 0;
+            }
+        }
+    }
+}
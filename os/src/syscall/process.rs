@@ -3,26 +3,25 @@
 use alloc::sync::Arc;
 
 use crate::{
-    config::{MAXVA, MAX_SYSCALL_NUM, PAGE_SIZE, TRAP_CONTEXT_BASE},
-    fs::{open_file, File, OpenFlags},
+    config::{MAXVA, MAX_SYSCALL_NUM, PAGE_SIZE},
+    fs::{open_file, OpenFlags},
     mm::{
-        translated_byte_buffer, translated_refmut, translated_str, MapPermission, MemorySet,
-        VPNRange, VirtAddr, KERNEL_SPACE,
+        copy_from_user, copy_to_user, translated_ref, translated_str, MapPermission, VPNRange,
+        VirtAddr,
     },
-    sync::UPSafeCell,
     task::{
         add_task, create_new_map_area, current_task, current_user_token, exit_current_and_run_next,
         get_current_task_page_table, get_current_task_status, get_current_task_syscall_times,
-        kstack_alloc, pid_alloc, suspend_current_and_run_next, unmap_consecutive_area, TaskContext,
-        TaskControlBlock, TaskControlBlockInner, TaskStatus,
+        pid2task, suspend_current_and_run_next, unmap_consecutive_area, SignalAction, SignalFlags,
+        TaskStatus, MAX_SIG,
     },
     timer::{get_time_ms, get_time_us},
-    trap::{trap_handler, TrapContext},
 };
+use alloc::string::String;
 use alloc::vec::Vec;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
@@ -30,6 +29,7 @@ pub struct TimeVal {
 
 /// Task information
 #[allow(dead_code)]
+#[derive(Clone, Copy)]
 pub struct TaskInfo {
     /// Task status in it's life cycle
     status: TaskStatus,
@@ -71,15 +71,27 @@ pub fn sys_fork() -> isize {
     new_pid as isize
 }
 
-pub fn sys_exec(path: *const u8) -> isize {
+pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     trace!("kernel:pid[{}] sys_exec", current_task().unwrap().pid.0);
     let token = current_user_token();
     let path = translated_str(token, path);
+    let mut args_vec: Vec<String> = Vec::new();
+    loop {
+        let arg_str_ptr = *translated_ref(token, args);
+        if arg_str_ptr == 0 {
+            break;
+        }
+        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+        unsafe {
+            args = args.add(1);
+        }
+    }
     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
         let all_data = app_inode.read_all();
         let task = current_task().unwrap();
-        task.exec(all_data.as_slice());
-        0
+        let argc = args_vec.len();
+        task.exec(all_data.as_slice(), args_vec);
+        argc as isize
     } else {
         -1
     }
@@ -115,7 +127,9 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // ++++ temporarily access child PCB exclusively
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+        if copy_to_user(inner.memory_set.token(), exit_code_ptr, &exit_code).is_err() {
+            return -1;
+        }
         found_pid as isize
     } else {
         -2
@@ -131,26 +145,14 @@ pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
         current_task().unwrap().pid.0
     );
     let us = get_time_us();
-    let dst_vec = translated_byte_buffer(
-        current_user_token(),
-        ts as *const u8,
-        core::mem::size_of::<TimeVal>(),
-    );
-    let ref time_val = TimeVal {
+    let time_val = TimeVal {
         sec: us / 1_000_000,
         usec: us % 1_000_000,
     };
-    let src_ptr = time_val as *const TimeVal;
-    for (idx, dst) in dst_vec.into_iter().enumerate() {
-        let unit_len = dst.len();
-        unsafe {
-            dst.copy_from_slice(core::slice::from_raw_parts(
-                src_ptr.wrapping_byte_add(idx * unit_len) as *const u8,
-                unit_len,
-            ));
-        }
+    match copy_to_user(current_user_token(), ts, &time_val) {
+        Ok(()) => 0,
+        Err(()) => -1,
     }
-    0
 }
 
 /// Implement in [CH3], re implement in [CH5] We re implement this function use the function as follow:
@@ -162,28 +164,15 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
         "kernel:pid[{}] sys_task_info NOT IMPLEMENTED",
         current_task().unwrap().pid.0
     );
-    let dst_vec = translated_byte_buffer(
-        current_user_token(),
-        ti as *const u8,
-        core::mem::size_of::<TaskInfo>(),
-    );
-    let ref task_info = TaskInfo {
+    let task_info = TaskInfo {
         status: get_current_task_status(),
         syscall_times: get_current_task_syscall_times(),
         time: get_time_ms(),
     };
-
-    let src_ptr = task_info as *const TaskInfo;
-    for (idx, dst) in dst_vec.into_iter().enumerate() {
-        let unit_len = dst.len();
-        unsafe {
-            dst.copy_from_slice(core::slice::from_raw_parts(
-                src_ptr.wrapping_byte_add(idx * unit_len) as *const u8,
-                unit_len,
-            ));
-        }
+    match copy_to_user(current_user_token(), ti, &task_info) {
+        Ok(()) => 0,
+        Err(()) => -1,
     }
-    0
 }
 /// Implement in [CH5], function `mmap()`.
 /// `Mmap` the mapped virtual address
@@ -260,89 +249,131 @@ pub fn sys_sbrk(size: i32) -> isize {
 /// * Process pool full/insufficient memory/resources error.
 pub fn sys_spawn(path: *const u8) -> isize {
     let task = current_task().unwrap();
-    let mut parent_inner = task.inner_exclusive_access();
-    let token = parent_inner.memory_set.token();
+    let token = task.inner_exclusive_access().memory_set.token();
     let path = translated_str(token, path);
     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
         let all_data = app_inode.read_all();
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(all_data.as_slice());
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-
-        // Alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = kstack_alloc();
-        let kernel_stack_top = kernel_stack.get_top();
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(&task)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: new_fd_table,
-                    heap_bottom: parent_inner.heap_bottom,
-                    program_brk: parent_inner.program_brk,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    user_time: 0,
-                    kernel_time: 0,
-                    checkpoint: get_time_ms(),
-                    stride: 0,
-                    priority: 16,
-                })
-            },
-        });
-
-        // Add child
-        parent_inner.children.push(task_control_block.clone());
-        // Prepare TrapContext in user space
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-
-        let pid = task_control_block.pid.0 as isize;
-        add_task(task_control_block);
+        let new_task = task.spawn(all_data.as_slice());
+        let pid = new_task.pid.0 as isize;
+        add_task(new_task);
         pid
     } else {
-        return -1;
+        -1
     }
 }
 
 /// `syscall ID:` 140
-/// Set the current process priority to `prio`
+/// Set the current process priority to `prio`, used by the stride scheduler
 /// **Parameter**: `prio` is the process priority, must be `prio >= 2`
 /// **Return value**: Returns `prio` if the input is valid; otherwise, `returns -1`.
 /// Implement in [CH5]
 pub fn sys_set_priority(prio: isize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_set_priority NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_set_priority",
         current_task().unwrap().pid.0
     );
     if prio <= 1 {
         return -1;
     }
     let task = current_task().unwrap();
-    task.inner.exclusive_access().set_priority(prio as u64);
+    task.set_priority(prio as u64);
     prio
 }
+
+/// `syscall ID`: 129
+/// Raise signal `signum` against the process with the given `pid`.
+/// **Return value**: `0` on success, `-1` if `pid` names no live process or
+/// `signum` is out of range.
+pub fn sys_kill(pid: usize, signum: i32) -> isize {
+    trace!("kernel: sys_kill");
+    if !(0..=MAX_SIG as i32).contains(&signum) {
+        return -1;
+    }
+    match pid2task(pid) {
+        Some(task) => match SignalFlags::from_bits(1 << signum) {
+            Some(flag) => {
+                task.inner_exclusive_access().signals.insert(flag);
+                0
+            }
+            None => -1,
+        },
+        None => -1,
+    }
+}
+
+/// `syscall ID`: 134
+/// Install a new handler for `signum`, optionally returning the previous
+/// one. `SIGKILL`/`SIGSTOP` cannot be handled. Either `action` or
+/// `old_action` may be null to skip that half of the operation.
+pub fn sys_sigaction(
+    signum: i32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    trace!("kernel: sys_sigaction");
+    if !(0..=MAX_SIG as i32).contains(&signum) {
+        return -1;
+    }
+    let flag = match SignalFlags::from_bits(1 << signum) {
+        Some(flag) => flag,
+        None => return -1,
+    };
+    if flag.intersects(SignalFlags::SIGKILL | SignalFlags::SIGSTOP) {
+        return -1;
+    }
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let signum = signum as usize;
+    let old = inner.signal_actions[signum];
+    if !old_action.is_null() && copy_to_user(token, old_action, &old).is_err() {
+        return -1;
+    }
+    if !action.is_null() {
+        let mut new_action = SignalAction::default();
+        if copy_from_user(token, action, &mut new_action).is_err() {
+            return -1;
+        }
+        inner.signal_actions[signum] = new_action;
+    }
+    0
+}
+
+/// `syscall ID`: 135
+/// Set the signal mask for the current task, returning the previous mask.
+/// Returns `-1` if `mask` contains bits outside the recognized signal range.
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    trace!("kernel: sys_sigprocmask");
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let old_mask = inner.signal_mask;
+    match SignalFlags::from_bits(mask) {
+        Some(flag) => {
+            inner.signal_mask = flag;
+            old_mask.bits() as isize
+        }
+        None => -1,
+    }
+}
+
+/// `syscall ID`: 139
+/// Return from a signal handler back into the interrupted code, restoring
+/// the `TrapContext` that was backed up when the handler was entered.
+/// Returns `-1` if the task wasn't actually inside a handler.
+pub fn sys_sigreturn() -> isize {
+    trace!("kernel: sys_sigreturn");
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.handling_sig = -1;
+    match inner.trap_ctx_backup.take() {
+        Some(trap_ctx_backup) => {
+            *inner.get_trap_cx() = trap_ctx_backup;
+            // The restored `TrapContext` already holds whatever the
+            // interrupted code had in `a0`; returning it here (instead of a
+            // fixed `0`) means the trap-return path's usual "write the
+            // syscall result into a0" doesn't clobber it.
+            inner.get_trap_cx().x[10] as isize
+        }
+        None => -1,
+    }
+}
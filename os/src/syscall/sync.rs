@@ -1,7 +1,10 @@
 use core::usize;
 
 use crate::config::TOTAL_AVAILABLE;
-use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
+use crate::sync::{
+    futex_wait, futex_wake, Completion, Condvar, Mutex, MutexBlocking, MutexBlockingPi, MutexSpin,
+    RwLock, Semaphore, FUTEX_WAIT, FUTEX_WAKE,
+};
 use crate::task::{block_current_and_run_next, current_process, current_task, ProcessControlBlock};
 use crate::timer::{add_timer, get_time_ms};
 use alloc::sync::Arc;
@@ -28,7 +31,12 @@ pub fn sys_sleep(ms: usize) -> isize {
     0
 }
 /// Mutex create `syscall`
-pub fn sys_mutex_create(blocking: bool) -> isize {
+///
+/// `priority_inheritance` only matters when `blocking` is set: it selects
+/// [`MutexBlockingPi`] over the plain [`MutexBlocking`], so a thread that
+/// knows it may be held across a priority-sensitive section can opt into
+/// paying for inheritance bookkeeping instead of it being on unconditionally.
+pub fn sys_mutex_create(blocking: bool, priority_inheritance: bool) -> isize {
     trace!(
         "kernel:pid[{}] tid[{}] sys_mutex_create",
         current_task().unwrap().process.upgrade().unwrap().getpid(),
@@ -43,6 +51,8 @@ pub fn sys_mutex_create(blocking: bool) -> isize {
     let process = current_process();
     let mutex: Option<Arc<dyn Mutex>> = if !blocking {
         Some(Arc::new(MutexSpin::new()))
+    } else if priority_inheritance {
+        Some(Arc::new(MutexBlockingPi::new()))
     } else {
         // 如果向量中有空的元素，就在这个空元素的位置创建一个可睡眠的互斥锁；
         Some(Arc::new(MutexBlocking::new()))
@@ -76,21 +86,32 @@ pub fn sys_mutex_lock(mutex_id: usize) -> isize {
             .unwrap()
             .tid
     );
+    let tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid;
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
 
     // Deadlock detection
     if process_inner.deadlock_detection_enabled {
-        // Implement deadlock detection check here
-        if deadlock_detected(&process) {
+        drop(process_inner);
+        if !request_is_safe(&process, tid, mutex_id, 1) {
             return -0xDEAD; // Deadlock detected
         }
+    } else {
+        drop(process_inner);
     }
-    drop(process_inner);
     drop(process);
     // 调用ID为`mutex_id`的互斥锁`mutex`的`lock`方法
     mutex.lock();
+    // The grant above passed the safety check (or detection is off); record
+    // it for real now that the lock has actually been taken.
+    current_process().inner_exclusive_access().allocation[tid][mutex_id] += 1;
     0
 }
 /// Mutex unlock `syscall`
@@ -106,6 +127,13 @@ pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
             .unwrap()
             .tid
     );
+    let tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid;
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
@@ -113,6 +141,142 @@ pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
     drop(process);
     // 调用ID为`mutex_id`的互斥锁`mutex`的`unlock`方法
     mutex.unlock();
+    current_process().inner_exclusive_access().allocation[tid][mutex_id] -= 1;
+    0
+}
+/// Mutex lock with timeout `syscall`: like [`sys_mutex_lock`], but gives up
+/// after `ms` milliseconds instead of blocking forever.
+///
+/// Returns `0` if the lock was acquired, `-2` if it timed out instead.
+pub fn sys_mutex_lock_timeout(mutex_id: usize, ms: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_mutex_lock_timeout",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid;
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+
+    // Deadlock detection
+    if process_inner.deadlock_detection_enabled {
+        drop(process_inner);
+        if !request_is_safe(&process, tid, mutex_id, 1) {
+            return -0xDEAD; // Deadlock detected
+        }
+    } else {
+        drop(process_inner);
+    }
+    drop(process);
+    if !mutex.lock_timeout(ms) {
+        return -2; // Timed out
+    }
+    current_process().inner_exclusive_access().allocation[tid][mutex_id] += 1;
+    0
+}
+/// Rwlock create `syscall`
+pub fn sys_rwlock_create() -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_create",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .rwlock_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.rwlock_list[id] = Some(Arc::new(RwLock::new()));
+        id
+    } else {
+        process_inner
+            .rwlock_list
+            .push(Some(Arc::new(RwLock::new())));
+        process_inner.rwlock_list.len() - 1
+    };
+    id as isize
+}
+/// Rwlock read lock `syscall`
+pub fn sys_rwlock_read_lock(rwlock_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_read_lock",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = Arc::clone(process_inner.rwlock_list[rwlock_id].as_ref().unwrap());
+    drop(process_inner);
+    rwlock.read_lock();
+    0
+}
+/// Rwlock write lock `syscall`
+pub fn sys_rwlock_write_lock(rwlock_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_write_lock",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = Arc::clone(process_inner.rwlock_list[rwlock_id].as_ref().unwrap());
+    drop(process_inner);
+    rwlock.write_lock();
+    0
+}
+/// Rwlock unlock `syscall`
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_unlock",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = Arc::clone(process_inner.rwlock_list[rwlock_id].as_ref().unwrap());
+    drop(process_inner);
+    rwlock.unlock();
     0
 }
 /// Semaphore create `syscall`
@@ -160,11 +324,19 @@ pub fn sys_semaphore_up(sem_id: usize) -> isize {
             .unwrap()
             .tid
     );
+    let tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid;
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
     drop(process_inner);
     sem.up();
+    current_process().inner_exclusive_access().allocation[tid][sem_id] -= 1;
     0
 }
 /// Semaphore down `syscall`
@@ -180,19 +352,70 @@ pub fn sys_semaphore_down(sem_id: usize) -> isize {
             .unwrap()
             .tid
     );
+    let tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid;
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
 
     // Deadlock detection
     if process_inner.deadlock_detection_enabled {
-        // Implement deadlock detection check here
-        if deadlock_detected(&process) {
+        drop(process_inner);
+        if !request_is_safe(&process, tid, sem_id, 1) {
             return -0xDEAD; // Deadlock detected
         }
+    } else {
+        drop(process_inner);
     }
-    drop(process_inner);
     sem.down();
+    current_process().inner_exclusive_access().allocation[tid][sem_id] += 1;
+    0
+}
+/// Semaphore down with timeout `syscall`: like [`sys_semaphore_down`], but
+/// gives up after `ms` milliseconds instead of blocking forever.
+///
+/// Returns `0` if an instance was acquired, `-2` if it timed out instead.
+pub fn sys_semaphore_down_timeout(sem_id: usize, ms: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_semaphore_down_timeout",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid;
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+
+    // Deadlock detection
+    if process_inner.deadlock_detection_enabled {
+        drop(process_inner);
+        if !request_is_safe(&process, tid, sem_id, 1) {
+            return -0xDEAD; // Deadlock detected
+        }
+    } else {
+        drop(process_inner);
+    }
+    if !sem.down_timeout(ms) {
+        return -2; // Timed out
+    }
+    current_process().inner_exclusive_access().allocation[tid][sem_id] += 1;
     0
 }
 /// Condvar create `syscall`
@@ -268,6 +491,110 @@ pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     condvar.wait(mutex);
     0
 }
+/// Completion create `syscall`
+pub fn sys_completion_create() -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_completion_create",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .completion_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.completion_list[id] = Some(Arc::new(Completion::new()));
+        id
+    } else {
+        process_inner
+            .completion_list
+            .push(Some(Arc::new(Completion::new())));
+        process_inner.completion_list.len() - 1
+    };
+    id as isize
+}
+/// Completion wait `syscall`
+pub fn sys_completion_wait(completion_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_completion_wait",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let completion = Arc::clone(
+        process_inner.completion_list[completion_id]
+            .as_ref()
+            .unwrap(),
+    );
+    drop(process_inner);
+    completion.wait();
+    0
+}
+/// Completion complete `syscall`: wake exactly one waiter
+pub fn sys_completion_complete(completion_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_completion_complete",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let completion = Arc::clone(
+        process_inner.completion_list[completion_id]
+            .as_ref()
+            .unwrap(),
+    );
+    drop(process_inner);
+    completion.complete();
+    0
+}
+/// Completion complete_all `syscall`: permanently mark done, wake every waiter
+pub fn sys_completion_complete_all(completion_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_completion_complete_all",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let completion = Arc::clone(
+        process_inner.completion_list[completion_id]
+            .as_ref()
+            .unwrap(),
+    );
+    drop(process_inner);
+    completion.complete_all();
+    0
+}
 /// Enable deadlock detection `syscall`
 ///
 /// YOUR JOB: Implement deadlock detection, but might not all in this syscall
@@ -288,94 +615,121 @@ pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
     0
 }
 
-/// Define Resource management structures
-pub struct ResourceManager {
-    allocation: Vec<Vec<usize>>,
-    max: Vec<Vec<usize>>,
-    available: Vec<usize>,
-    _num_processes: usize,
-    _num_resources: usize,
-}
-
-impl ResourceManager {
-    pub fn new(num_processes: usize, num_resources: usize) -> Self {
-        Self {
-            allocation: vec![vec![0; num_resources]; num_processes],
-            max: vec![vec![0; num_resources]; num_processes],
-            available: vec![0; num_resources],
-            _num_processes: num_processes,
-            _num_resources: num_resources,
-        }
-    }
-
-    pub fn update_allocation(&mut self, process_id: usize, resources: Vec<usize>) {
-        self.allocation[process_id] = resources;
-    }
-
-    pub fn update_max(&mut self, process_id: usize, max_resource: Vec<usize>) {
-        self.max[process_id] = max_resource;
-    }
-
-    pub fn set_available(&mut self, available: Vec<usize>) {
-        self.available = available;
-    }
-}
-
-/// Implement in [CH8]
-fn deadlock_detected(process: &Arc<ProcessControlBlock>) -> bool {
-    let process_inner = process.inner_exclusive_access();
+/// Check whether granting `amount` units of resource `resource_id` to task
+/// `tid` would leave the system in a safe state, via the full Banker's
+/// algorithm: tentatively apply the grant, run the safety loop against that
+/// state, then always roll the grant back - the real grant is applied by the
+/// caller only if this returns `true`.
+///
+/// Unlike the old `deadlock_detected`, which only ran the safety check on
+/// whatever `process_inner.allocation`/`max` already held, this checks the
+/// state *after* the pending request - the only way to actually refuse a
+/// request that would create an unsafe state, rather than notice after the
+/// fact that one already has.
+///
+/// NOTE: `mutex_id` and `sem_id` are each a separate, independently-growing
+/// id space (see `sys_mutex_create`/`sys_semaphore_create`), but
+/// `process_inner.allocation`/`max` here are indexed by a single
+/// `resource_id` shared across both. That ambiguity predates this change -
+/// `deadlock_detected` read from the very same `allocation`/`max` fields -
+/// and resolving it for real needs a combined resource-id space threaded
+/// through `sys_mutex_create`/`sys_semaphore_create`, which is out of scope
+/// here.
+fn request_is_safe(
+    process: &Arc<ProcessControlBlock>,
+    tid: usize,
+    resource_id: usize,
+    amount: usize,
+) -> bool {
+    let mut process_inner = process.inner_exclusive_access();
     let num_processes = process_inner.num_processes;
-    let num_resources = process_inner.num_resources;
 
-    let mut resource_manager = ResourceManager::new(num_processes, num_resources);
+    let need_of = |max: &[Vec<usize>], allocation: &[Vec<usize>], i: usize| -> Vec<usize> {
+        max[i]
+            .iter()
+            .zip(allocation[i].iter())
+            .map(|(m, a)| m - a)
+            .collect()
+    };
+    let available_with = |allocation: &[Vec<usize>]| -> Vec<usize> {
+        let mut available = TOTAL_AVAILABLE.to_vec();
+        for row in allocation {
+            for (j, used) in row.iter().enumerate() {
+                available[j] -= used;
+            }
+        }
+        available
+    };
 
-    // Populate resource_manager with current allocation and max
-    for i in 0..num_processes {
-        resource_manager.update_allocation(i, process_inner.allocation[i].clone());
-        resource_manager.update_max(i, process_inner.max[i].clone());
+    if amount > need_of(&process_inner.max, &process_inner.allocation, tid)[resource_id]
+        || amount > available_with(&process_inner.allocation)[resource_id]
+    {
+        return false;
     }
 
-    // Calculate the Need matrix
-    let need: Vec<Vec<usize>> = resource_manager
-        .allocation
-        .iter()
-        .zip(resource_manager.max.iter())
-        .map(|(alloc, max)| max.iter().zip(alloc.iter()).map(|(m, a)| m - a).collect())
-        .collect();
+    // Tentatively apply the grant.
+    process_inner.allocation[tid][resource_id] += amount;
 
-    // Update available resources based on current allocations
-    let mut available = vec![0; num_resources];
-    for j in 0..num_resources {
-        available[j] = TOTAL_AVAILABLE[j];
-        for i in 0..num_processes {
-            available[j] -= resource_manager.allocation[i][j];
-        }
-    }
-    resource_manager.set_available(available);
-
-    // Work array represents the resources available to complete processes
-    let mut work = resource_manager.available.clone();
+    let mut work = available_with(&process_inner.allocation);
     let mut finish = vec![false; num_processes];
-
-    loop {
+    let safe = loop {
         let mut made_progress = false;
         for i in 0..num_processes {
-            if !finish[i] && need[i].iter().zip(work.iter()).all(|(n, w)| n <= w) {
-                // Process i can finish
-                for j in 0..num_resources {
-                    work[j] += resource_manager.allocation[i][j];
+            if !finish[i]
+                && need_of(&process_inner.max, &process_inner.allocation, i)
+                    .iter()
+                    .zip(work.iter())
+                    .all(|(n, w)| n <= w)
+            {
+                for (j, allocated) in process_inner.allocation[i].iter().enumerate() {
+                    work[j] += allocated;
                 }
-                finish[i] = true; // Mark process as finished
+                finish[i] = true;
                 made_progress = true;
             }
         }
-        // If no process is made, we're in a deadlock
+        if finish.iter().all(|&f| f) {
+            break true;
+        }
         if !made_progress {
-            return true; // Deadlock detected
+            break false;
         }
-        // Check if all processes are finished
-        if finish.iter().all(|&f| f) {
-            return false; // No deadlock
+    };
+
+    // Always roll the tentative grant back - the real one happens only once
+    // `Mutex::lock`/`Semaphore::down` is actually called by the caller below.
+    process_inner.allocation[tid][resource_id] -= amount;
+
+    safe
+}
+
+/// Futex `syscall`: a general-purpose blocking primitive keyed on an
+/// arbitrary user address rather than a pre-registered `mutex_id`, so
+/// userspace only traps into the kernel once a lock actually contends.
+///
+/// `timeout` is accepted for interface symmetry with the real Linux
+/// `futex(2)` but is not yet wired up to the timer wheel - see
+/// `sys_mutex_lock_timeout`/`sys_semaphore_down_timeout` for the one place
+/// in this kernel that already does that integration.
+pub fn sys_futex(uaddr: usize, op: usize, val: u32, _timeout: usize, bitset: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_futex",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let uaddr = uaddr as *const u32;
+    match op {
+        FUTEX_WAIT => {
+            futex_wait(uaddr, val, bitset);
+            0
         }
+        FUTEX_WAKE => futex_wake(uaddr, val, bitset) as isize,
+        _ => -1,
     }
 }
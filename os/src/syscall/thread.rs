@@ -84,6 +84,22 @@ pub fn sys_gettid() -> isize {
         .tid as isize
 }
 
+// NOTE: 这里本来想把`sys_waittid`从轮询改成阻塞：目标线程还没退出时，把调用者
+// 记在目标`TaskControlBlockInner`新增的等待者列表里，并用`task::manager::remove`
+// 或等价操作把调用者从就绪队列里摘掉；目标线程退出时再排干这份等待者列表，把
+// 它们一个个`add_task`回去重新跑一遍`sys_waittid`，直到这次能真正收到退出码为止。
+// 但这个文件里`sys_waittid`/`sys_thread_create`/`sys_gettid`已经假设了一套这棵
+// 树里实际不存在的架构——`TaskControlBlock.process: Weak<ProcessControlBlock>`、
+// `ProcessControlBlockInner.tasks: Vec<Option<Arc<TaskControlBlock>>>`、
+// `TaskControlBlockInner.res: Option<TaskUserRes>`（帶`tid`）、以及接收
+// `(process, ustack_base, alloc_user_res)`三个参数的`TaskControlBlock::new`——
+// 而`task/task.rs`里真正的`TaskControlBlock`仍然是"一个进程恰好一个线程"的模型，
+// 没有`ProcessControlBlock`，也没有这些字段。也就是说这个文件本身在基线快照里
+// 就没法编译，不是这次改动引入的缺口。和`sync`子系统里记录的那个缺口是同一个
+// 缺口（见`sync/mod.rs`），在那套进程/线程分离的模型被真正建出来之前，这里没有
+// 地方可以挂一个真实的等待者列表，贸然现编一套`ProcessControlBlock`会动到这个
+// 会话里搭起来的大半个单TCB任务模型，而且没有编译环境能验证对不对，所以按
+// 既定的做法把这个缺口显式记下来，而不是假装修好它。
 /// Wait for a thread to exit `syscall`
 ///
 /// Thread does not exist, return -1
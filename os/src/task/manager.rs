@@ -1,62 +1,52 @@
 //!Implementation of [`TaskManager`]
-use super::{TaskControlBlock, TaskStatus};
-use crate::config::BIG_STRIDE;
+use super::scheduler::{Scheduler, StrideScheduler};
+use super::TaskControlBlock;
 use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
-use alloc::sync::Arc;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
 use lazy_static::*;
+
+// NOTE: `TaskManager`不再直接持有队列，而是把排队策略委托给一个
+// `Box<dyn Scheduler<Arc<TaskControlBlock>>>`，这样换一种调度算法只需要
+// 在构造`TaskManager`时换一个具体类型，而不用动 switch/trap 的配套代码。
 ///A array of `TaskControlBlock` that is thread-safe
+///
+/// The actual ordering policy lives behind the [`Scheduler`] trait, boxed so
+/// the policy can be swapped without changing this type.
 pub struct TaskManager {
-    // NOTE: 将所有的任务控制块用引用计数`Arc`智能指针包裹后放在一个双端队列`VecDeque`中
-    // 使用智能指针的原因在于，
-    // 1. 任务控制块经常需要被放入/取出，
-    //  如果直接移动任务控制块自身将会带来大量的数据拷贝开销，
-    //  而对于智能指针进行移动则没有多少开销。
-    // 2. 允许任务控制块的共享引用在某些情况下能够让我们的实现更加方便。
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    scheduler: Box<dyn Scheduler<Arc<TaskControlBlock>>>,
 }
 
-// NOTE: 在这里，add和fetch组合形成了最简单的RR算法
-/// A simple FIFO scheduler.
 impl TaskManager {
-    ///Create an empty TaskManager
+    /// Create a `TaskManager` running the default scheduling policy.
+    ///
+    /// This is the boot-time policy choice: swap the `Box::new(..)` below
+    /// (or route it through a kernel cmdline flag once this tree grows a
+    /// real one) to boot with a different [`Scheduler`] impl.
     pub fn new() -> Self {
-        Self {
-            ready_queue: VecDeque::new(),
-        }
+        Self::with_scheduler(Box::new(StrideScheduler::new()))
+    }
+
+    /// Create a `TaskManager` around an explicit scheduling policy.
+    pub fn with_scheduler(scheduler: Box<dyn Scheduler<Arc<TaskControlBlock>>>) -> Self {
+        Self { scheduler }
     }
-    // NOTE: 将一个任务加入队尾
+
     /// Add process back to ready queue
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        self.scheduler.insert(task);
     }
 
-    // NOTE: 从队头中取出一个任务来执行
-    /// Implement in [CH5]
     /// Take a process out of the ready queue
-    /// In this function, the `stride strategy` is implemented to replace the basic `FIFO strategy`.
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        // FIFO strategy
-        // `self.ready_queue.pop_front()`
-
-        // Stride strategy
-        let mut min_index = 0;
-        let mut min_stride = 0x7FFF_FFFF;
-        for (idx, task) in self.ready_queue.iter().enumerate() {
-            let inner = task.inner.exclusive_access();
-            if inner.get_status() == TaskStatus::Ready {
-                if inner.stride < min_stride {
-                    min_stride = inner.stride;
-                    min_index = idx;
-                }
-            }
-        }
+        self.scheduler.pop()
+    }
 
-        if let Some(task) = self.ready_queue.get(min_index) {
-            let mut inner = task.inner.exclusive_access();
-            inner.stride += BIG_STRIDE / inner.priority;
-        }
-        self.ready_queue.remove(min_index)
+    /// Remove a specific process from the ready queue (e.g. it was chosen
+    /// to be killed while still waiting to run)
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
+        self.scheduler.remove(task)
     }
 }
 
@@ -77,3 +67,40 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
+
+// NOTE: 给内核其他的子模块提供的函数
+/// Remove a specific process from the ready queue without running it
+pub fn remove_task(task: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.exclusive_access().remove(task)
+}
+
+// NOTE: `sys_kill`需要能按`pid`找到任意进程的`TaskControlBlock`，而不仅仅是
+// 当前进程及其子进程，所以这里单独维护一张全局的`pid -> TCB`表。用`Weak`
+// 而非`Arc`持有是为了不影响被指向任务自身的引用计数（和`TaskControlBlockInner::parent`
+// 的理由一样）。
+lazy_static! {
+    /// Global `pid -> TaskControlBlock` registry, used to locate any live
+    /// task by pid (e.g. for `sys_kill`)
+    static ref PID2TASK: UPSafeCell<BTreeMap<usize, Weak<TaskControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Register a newly created task under its pid
+pub fn insert_into_pid2task(pid: usize, task: &Arc<TaskControlBlock>) {
+    PID2TASK
+        .exclusive_access()
+        .insert(pid, Arc::downgrade(task));
+}
+
+/// Drop the registration for an exiting task
+pub fn remove_from_pid2task(pid: usize) {
+    PID2TASK.exclusive_access().remove(&pid);
+}
+
+/// Find a live task by pid
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    PID2TASK
+        .exclusive_access()
+        .get(&pid)
+        .and_then(Weak::upgrade)
+}
@@ -17,7 +17,10 @@ mod context;
 mod id;
 mod manager;
 mod processor;
+mod scheduler;
+mod signal;
 mod switch;
+mod wait_queue;
 #[allow(clippy::module_inception)]
 #[allow(rustdoc::private_intra_doc_links)]
 mod task;
@@ -31,12 +34,18 @@ use alloc::sync::Arc;
 pub use context::TaskContext;
 use lazy_static::*;
 pub use manager::{fetch_task, TaskManager};
+pub use scheduler::{FifoScheduler, Scheduler, StrideScheduler, BIG_STRIDE};
+pub use signal::{SignalAction, SignalFlags, MAX_SIG};
+pub use wait_queue::{block_current_and_run_next, WaitQueue};
 use switch::__switch;
 /// Change in [CH5], add `TaskControlBlockInner` as pub
-pub use task::{TaskControlBlock, TaskControlBlockInner, TaskStatus};
+pub use task::{
+    TaskControlBlock, TaskControlBlockInner, TaskStatus, VmAreaDescriptor, DEFAULT_PRIORITY,
+    DEFAULT_TIME_SLICE,
+};
 
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
-pub use manager::add_task;
+pub use manager::{add_task, insert_into_pid2task, pid2task, remove_from_pid2task, remove_task};
 pub use processor::{
     current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
     Processor,
@@ -60,6 +69,35 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+// NOTE: 这应当在每次时钟中断里被调用一次——在`trap_handler`的
+// `Trap::Interrupt(Interrupt::SupervisorTimer)`分支，`set_next_trigger`之后、
+// 返回用户态之前——和`user_time_start`/`user_time_end`一样用`checkpoint`记账，
+// 这样抢占和已有的内核态/用户态计时不会互相打架。由于这棵树里真正的中断分发器
+// （`trap/mod.rs`）还没有实现，这里先把“时间片耗尽就抢占”的决策逻辑准备好，
+// 等`trap_handler`补上之后直接在对应分支里调用它即可。
+/// Decrement the current task's time slice by one tick; once it's
+/// exhausted, force a switch to the next ready task (it goes back onto the
+/// ready queue through `add_task`, same as a cooperative yield).
+pub fn check_preempt() {
+    let task = current_task().unwrap();
+    let exhausted = task.tick();
+    drop(task);
+    if exhausted {
+        suspend_current_and_run_next();
+    }
+}
+
+/// Move a blocked task back onto the ready queue, mirroring
+/// [`WaitQueue::wake_one`](wait_queue::WaitQueue::wake_one). Used by the
+/// `sync` module's blocking primitives (`Mutex`, `Semaphore`, `Condvar`) to
+/// hand a resource off to the thread that was waiting longest for it.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
+
 /// pid of usertests app in make run TEST=1
 pub const IDLE_PID: usize = 0;
 
@@ -102,6 +140,8 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     inner.fd_table.clear();
     drop(inner);
     // **** release current PCB
+    // this pid can no longer be the target of `sys_kill`
+    remove_from_pid2task(pid);
     // drop task manually to maintain rc correctly
     drop(task);
     // we do not have to save task context
@@ -123,6 +163,7 @@ lazy_static! {
 
 ///Add init process to the manager
 pub fn add_initproc() {
+    insert_into_pid2task(INITPROC.getpid(), &INITPROC);
     add_task(INITPROC.clone());
 }
 
@@ -184,13 +225,95 @@ pub fn get_current_task_page_table(vpn: VirtPageNum) -> Option<PageTableEntry> {
     task_inner.memory_set.translate(vpn)
 }
 
-/// Implement in [CH5]
+// NOTE: 不再立刻调用`insert_framed_area`分配物理页帧，而是只登记一个逻辑上的
+// `VmAreaDescriptor`；物理页帧的分配被推迟到第一次访问触发缺页异常、由
+// `handle_page_fault`处理的时候，这样一次很大的稀疏`mmap`不会立刻把内存吃光。
+/// Implement in [CH5], now lazy/demand-paged: records the region without
+/// allocating frames
 pub fn create_new_map_area(start_va: VirtAddr, end_va: VirtAddr, perm: MapPermission) {
     let task = current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
+    task_inner
+        .vm_areas
+        .push(VmAreaDescriptor::new(start_va, end_va, perm));
+}
+
+// NOTE: 这里只实现缺页异常本身的处理逻辑；这棵源码树里没有trap分发器
+// （trap/mod.rs缺失，只剩下trap/context.rs），所以调用点需要在它存在之后
+// 把`StorePageFault`/`LoadPageFault`/`InstructionPageFault`按需接到这里，
+// 传入`stval`对应的虚拟地址，并在返回`true`时重新执行引发异常的那条指令，
+// 返回`false`时按非法访问处理（杀掉进程）。
+/// Page-fault handler for the lazily-backed regions registered by
+/// `create_new_map_area`. Returns `true` if `va` fell inside a registered
+/// region with matching permission and a frame has now been mapped in (the
+/// faulting instruction should be retried); `false` if there is no such
+/// region or the access violates its permission (the caller should treat
+/// this as an illegal access).
+pub fn handle_page_fault(va: VirtAddr, is_store: bool) -> bool {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let vpn = va.floor();
+    let vma = match task_inner.find_vma(vpn) {
+        Some(vma) if !is_store || vma.perm.contains(MapPermission::W) => vma.clone(),
+        _ => return false,
+    };
+    let page_start = VirtAddr::from(vpn);
+    let page_end = VirtAddr::from(VirtPageNum(vpn.0 + 1));
+    // `insert_framed_area` maps through `PageTable::map`, which now flushes
+    // just this vpn for this address space's own ASID - no need to fence here.
     task_inner
         .memory_set
-        .insert_framed_area(start_va, end_va, perm);
+        .insert_framed_area(page_start, page_end, vma.perm);
+    true
+}
+
+// NOTE: trap分发器（trap/mod.rs里的trap_handler/trap_return）整个缺失，这棵
+// 源码树目前只剩下trap/context.rs，没法从这里把这个函数真正接到"每次从内核
+// 返回用户态之前"这个点上。等trap分发器存在之后，在trap_return里服务完当前
+// 的syscall/中断、真正`sret`回用户态之前调用一次这个函数即可；如果调用后
+// 发现`task.inner_exclusive_access().killed`为真，调用方应改为调用
+// `exit_current_and_run_next`而不是返回用户态。
+/// Scan the current task's pending-and-unmasked signals and act on the
+/// first one found: a signal with no registered handler either is ignored
+/// (if not fatal) or marks the task `killed` (if fatal, e.g. `SIGKILL`/
+/// `SIGSEGV`); a signal with a registered handler backs up the current
+/// `TrapContext`, diverts `sepc` to the handler with the signal number in
+/// `a0`, and returns so the caller resumes in user space inside the
+/// handler (which returns via `sys_sigreturn`).
+pub fn handle_signals() {
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        if inner.handling_sig >= 0 {
+            // Already inside a handler; don't re-enter one until
+            // `sys_sigreturn` clears this.
+            return;
+        }
+        let pending = inner.signals & !inner.signal_mask;
+        let signum = match (0..=MAX_SIG)
+            .find(|&i| SignalFlags::from_bits(1 << i).map_or(false, |f| pending.contains(f)))
+        {
+            Some(signum) => signum,
+            None => return,
+        };
+        let flag = SignalFlags::from_bits(1 << signum).unwrap();
+        inner.signals.remove(flag);
+        let action = inner.signal_actions[signum];
+        if action.handler == 0 {
+            if flag == SignalFlags::SIGKILL || flag == SignalFlags::SIGSEGV {
+                inner.killed = true;
+            }
+            // Otherwise ignored by default; keep scanning for more.
+            continue;
+        }
+        inner.handling_sig = signum as isize;
+        inner.signal_mask |= action.mask;
+        let trap_cx = inner.get_trap_cx();
+        inner.trap_ctx_backup = Some(*trap_cx);
+        trap_cx.sepc = action.handler;
+        trap_cx.x[10] = signum;
+        return;
+    }
 }
 
 /// Implement in [CH5]
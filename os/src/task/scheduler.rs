@@ -0,0 +1,163 @@
+//! Pluggable ready-queue scheduling policies.
+//!
+//! Modeled on tornado-os's shared scheduler: the switch/trap plumbing only
+//! ever talks to a `Scheduler<T>`, so `TaskManager`/`Processor` don't need to
+//! know which concrete policy (FIFO, stride, ...) is backing the ready queue.
+
+use super::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// A pluggable scheduling policy over ready tasks of type `T`.
+///
+/// `T: PartialEq` is required so [`remove`](Scheduler::remove) can locate a
+/// specific task anywhere in the queue, not just at the front.
+pub trait Scheduler<T: PartialEq> {
+    /// Add a task to the scheduler. Returns `Some(task)` if it could not be
+    /// accepted (`FifoScheduler` and `StrideScheduler` always accept).
+    fn insert(&mut self, task: T) -> Option<T>;
+
+    /// Look at the next task that would be returned by `pop`, without
+    /// removing it from the scheduler.
+    fn peek(&self) -> Option<&T>;
+
+    /// Mutable access to the next task that would be returned by `pop`.
+    ///
+    /// Note for implementers backed by a real binary heap (e.g.
+    /// `alloc::collections::BinaryHeap`): handing out a bare `&mut T` into
+    /// a heap's root lets a caller mutate the ordering key without
+    /// re-sifting, silently corrupting the heap - this is exactly why
+    /// std's own `BinaryHeap::peek_mut` returns a guard (`PeekMut`) that
+    /// re-sifts on `Drop` rather than a plain reference. A `Scheduler` impl
+    /// over a real heap would need the same kind of guard instead of this
+    /// signature; see [`StrideScheduler`] for why that tradeoff hasn't been
+    /// forced yet.
+    fn peek_mut(&mut self) -> Option<&mut T>;
+
+    /// Remove and return the next task that should run.
+    fn pop(&mut self) -> Option<T>;
+
+    /// Remove and return `task`, wherever it sits in the ready queue.
+    /// Returns `None` if it isn't queued.
+    fn remove(&mut self, task: &T) -> Option<T>;
+}
+
+/// A simple FIFO scheduler backed by a `VecDeque`.
+///
+/// Preserves plain round-robin behavior; pass one to
+/// [`TaskManager::with_scheduler`](super::manager::TaskManager::with_scheduler)
+/// to boot without stride scheduling. `TaskManager::new` currently boots
+/// with [`StrideScheduler`] instead, but swapping it for a `FifoScheduler`
+/// here is exactly the seam this trait exists to provide.
+pub struct FifoScheduler<T> {
+    ready_queue: VecDeque<T>,
+}
+
+impl<T> FifoScheduler<T> {
+    /// Create an empty FIFO scheduler
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<T: PartialEq> Scheduler<T> for FifoScheduler<T> {
+    fn insert(&mut self, task: T) -> Option<T> {
+        self.ready_queue.push_back(task);
+        None
+    }
+
+    fn peek(&self) -> Option<&T> {
+        self.ready_queue.front()
+    }
+
+    fn peek_mut(&mut self) -> Option<&mut T> {
+        self.ready_queue.front_mut()
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.ready_queue.pop_front()
+    }
+
+    fn remove(&mut self, task: &T) -> Option<T> {
+        let idx = self.ready_queue.iter().position(|queued| queued == task)?;
+        self.ready_queue.remove(idx)
+    }
+}
+
+/// Scaling factor for the stride-scheduling `pass = BIG_STRIDE / priority`
+/// step. Must stay large relative to the maximum priority so that the
+/// wrap-aware comparison below keeps holding.
+pub const BIG_STRIDE: u64 = 0x10000;
+
+/// Stride-scheduling policy: always runs the ready task with the smallest
+/// accumulated `stride`, then advances that task's stride by its `pass`.
+///
+/// `stride` is a `u64` that is expected to wrap around over the lifetime of
+/// a long-running task; comparisons use the wrap-aware rule
+/// `(a - b) as i64 > 0`, which stays correct as long as no two ready
+/// strides ever drift apart by more than `i64::MAX`.
+///
+/// Backed by a linear `Vec` scanned in `min_stride_index`, so `pop`/`peek`
+/// are `O(n)` rather than the `O(log n)` a binary heap would give. A
+/// `BinaryHeap` (the same type `timer.rs` already uses for its min-heap)
+/// was tried and backed out: it can't support `Scheduler::peek_mut`'s
+/// `&mut T` signature without risking silent heap corruption (see the note
+/// there). Nothing in this tree currently calls `peek`/`peek_mut` on a
+/// `Scheduler`, so the `O(n)` scan is correct as written today; revisit
+/// once something actually exercises that path, rather than reworking the
+/// trait for a benefit nothing here yet needs.
+pub struct StrideScheduler {
+    ready_queue: Vec<Arc<TaskControlBlock>>,
+}
+
+impl StrideScheduler {
+    /// Create an empty stride scheduler
+    pub fn new() -> Self {
+        Self {
+            ready_queue: Vec::new(),
+        }
+    }
+
+    /// Index of the ready task with the smallest stride, wrap-aware
+    fn min_stride_index(&self) -> Option<usize> {
+        self.ready_queue
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let diff = a.get_stride().wrapping_sub(b.get_stride()) as i64;
+                diff.cmp(&0)
+            })
+            .map(|(idx, _)| idx)
+    }
+}
+
+impl Scheduler<Arc<TaskControlBlock>> for StrideScheduler {
+    fn insert(&mut self, task: Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.push(task);
+        None
+    }
+
+    fn peek(&self) -> Option<&Arc<TaskControlBlock>> {
+        self.min_stride_index().map(|idx| &self.ready_queue[idx])
+    }
+
+    fn peek_mut(&mut self) -> Option<&mut Arc<TaskControlBlock>> {
+        let idx = self.min_stride_index()?;
+        Some(&mut self.ready_queue[idx])
+    }
+
+    fn pop(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let idx = self.min_stride_index()?;
+        let task = self.ready_queue.remove(idx);
+        task.add_pass();
+        Some(task)
+    }
+
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
+        let idx = self.ready_queue.iter().position(|queued| queued == task)?;
+        Some(self.ready_queue.remove(idx))
+    }
+}
@@ -0,0 +1,66 @@
+//! Per-process signal state: pending/masked signals and registered handlers
+use bitflags::*;
+
+bitflags! {
+    /// Pending/masked signal bits. Bit `i` corresponds to signal number `i`,
+    /// mirroring a (small) subset of the POSIX signal numbers.
+    pub struct SignalFlags: u32 {
+        /// Hangup
+        const SIGHUP    = 1 << 1;
+        /// Interrupt (Ctrl-C)
+        const SIGINT    = 1 << 2;
+        /// Quit
+        const SIGQUIT   = 1 << 3;
+        /// Illegal instruction
+        const SIGILL    = 1 << 4;
+        /// Trace/breakpoint trap
+        const SIGTRAP   = 1 << 5;
+        /// Abort
+        const SIGABRT   = 1 << 6;
+        /// Bus error
+        const SIGBUS    = 1 << 7;
+        /// Floating point exception
+        const SIGFPE    = 1 << 8;
+        /// Kill, cannot be caught, blocked, or ignored
+        const SIGKILL   = 1 << 9;
+        /// User-defined signal 1
+        const SIGUSR1   = 1 << 10;
+        /// Segmentation fault
+        const SIGSEGV   = 1 << 11;
+        /// User-defined signal 2
+        const SIGUSR2   = 1 << 12;
+        /// Broken pipe
+        const SIGPIPE   = 1 << 13;
+        /// Alarm clock
+        const SIGALRM   = 1 << 14;
+        /// Termination
+        const SIGTERM   = 1 << 15;
+        /// Child status has changed
+        const SIGCHLD   = 1 << 17;
+        /// Continue
+        const SIGCONT   = 1 << 18;
+        /// Stop, cannot be caught, blocked, or ignored
+        const SIGSTOP   = 1 << 19;
+    }
+}
+
+/// Highest signal number this kernel recognizes
+pub const MAX_SIG: usize = 31;
+
+/// A registered handler for one signal number, installed by `sys_sigaction`
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    /// Address of the user handler; `0` means "use the default disposition"
+    pub handler: usize,
+    /// Signals to additionally mask while this handler is running
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: 0,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
@@ -1,10 +1,16 @@
 //! Types related to task management & Functions for completely changing TCB
+use super::signal::{SignalAction, SignalFlags, MAX_SIG};
 use super::TaskContext;
-use super::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
-use crate::config::TRAP_CONTEXT_BASE;
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use super::{insert_into_pid2task, kstack_alloc, pid_alloc, KernelStack, PidHandle};
+use crate::config::{MAX_SYSCALL_NUM, TRAP_CONTEXT_BASE};
+use crate::fs::{File, Stdin, Stdout};
+use crate::mm::{
+    translated_refmut, MapPermission, MemorySet, PhysPageNum, VirtAddr, VirtPageNum, KERNEL_SPACE,
+};
 use crate::sync::UPSafeCell;
+use crate::timer::get_time_ms;
 use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::cell::RefMut;
@@ -26,6 +32,15 @@ pub struct TaskControlBlock {
     inner: UPSafeCell<TaskControlBlockInner>,
 }
 
+// NOTE: `Scheduler::remove`需要能在队列里定位某个具体的任务，这里用`pid`
+// 判断身份（两个`TaskControlBlock`永远不会共享同一个`pid`），而不是比较
+// 内部可变状态。
+impl PartialEq for TaskControlBlock {
+    fn eq(&self, other: &Self) -> bool {
+        self.pid.0 == other.pid.0
+    }
+}
+
 impl TaskControlBlock {
     // NOTE: 尝试获取互斥锁来得到`TaskControlBlockInner`的可变引用
     /// Get the mutable reference of the inner TCB
@@ -37,6 +52,37 @@ impl TaskControlBlock {
         let inner = self.inner_exclusive_access();
         inner.memory_set.token()
     }
+    /// Get the accumulated stride used by the stride scheduler
+    pub fn get_stride(&self) -> u64 {
+        self.inner_exclusive_access().stride
+    }
+    /// Advance `stride` by `BIG_STRIDE / priority`, as prescribed by the
+    /// stride-scheduling algorithm
+    pub fn add_pass(&self) {
+        let mut inner = self.inner_exclusive_access();
+        let priority = inner.priority;
+        inner.stride = inner
+            .stride
+            .wrapping_add(super::scheduler::BIG_STRIDE / priority);
+    }
+    /// Set the stride-scheduling priority, rejecting values below 2
+    pub fn set_priority(&self, priority: u64) {
+        self.inner_exclusive_access().set_priority(priority);
+    }
+    /// Decrement the remaining time slice by one tick. Returns `true` once
+    /// it reaches zero, immediately resetting it to `DEFAULT_TIME_SLICE` so
+    /// the next quantum starts fresh regardless of what the caller does
+    /// with the return value.
+    pub fn tick(&self) -> bool {
+        let mut inner = self.inner_exclusive_access();
+        inner.time_slice -= 1;
+        if inner.time_slice == 0 {
+            inner.time_slice = DEFAULT_TIME_SLICE;
+            true
+        } else {
+            false
+        }
+    }
 }
 
 pub struct TaskControlBlockInner {
@@ -85,6 +131,102 @@ pub struct TaskControlBlockInner {
 
     /// Program break
     pub program_brk: usize,
+
+    // NOTE: 用于stride调度算法，参见[`super::scheduler::StrideScheduler`]
+    /// Stride scheduling: accumulated stride of this task
+    pub stride: u64,
+
+    /// Stride scheduling: scheduling priority, must be `>= 2`
+    pub priority: u64,
+
+    // NOTE: 每个系统调用的被调用次数，为`sys_task_info`之类的接口服务
+    /// Number of times each syscall has been invoked by this task
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+
+    // NOTE: `checkpoint`记录上一次`update_checkpoint`被调用时的墙钟时间（毫秒）；
+    // `user_time_start`/`user_time_end`各自从它求出经过的时间并累加进
+    // `kernel_time`/`user_time`，从而精确区分这段时间是花在内核态还是用户态。
+    /// Accumulated time (ms) this task has spent running in the kernel
+    pub kernel_time: usize,
+    /// Accumulated time (ms) this task has spent running in user space
+    pub user_time: usize,
+    /// Wall-clock timestamp (ms) `kernel_time`/`user_time` were last updated from
+    pub checkpoint: usize,
+
+    // NOTE: 抢占式时间片轮转调度用：每次时钟中断递减一次，归零就重置为
+    // `DEFAULT_TIME_SLICE`并强制调用`suspend_current_and_run_next`，
+    // 参见[`super::check_preempt`]。
+    /// Remaining timer ticks before this task is preempted
+    pub time_slice: usize,
+
+    // NOTE: fd 0/1/2在`new`里预置为`Stdin`/`Stdout`/`Stdout`；`None`表示
+    // 该槽位空闲，可以被`alloc_fd`或`sys_dup2`重新利用。
+    /// File descriptor table: `fd_table[fd]` is `None` for a free slot
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+
+    // NOTE: 懒分页用：`sys_mmap`注册的区域只记录在这里，并不会立刻调用
+    // `memory_set.insert_framed_area`去分配物理页帧；真正的分配被推迟到
+    // 第一次访问触发缺页异常、由`super::handle_page_fault`处理的时候。
+    /// Lazily-backed regions of this task's address space (akin to
+    /// `vm_area_struct`): frames are allocated on first access, not up
+    /// front. See [`super::handle_page_fault`].
+    pub vm_areas: Vec<VmAreaDescriptor>,
+
+    /// Signals raised against this task but not yet delivered
+    pub signals: SignalFlags,
+    /// Signals currently masked (blocked) from delivery
+    pub signal_mask: SignalFlags,
+    /// Per-signal-number registered actions, installed by `sys_sigaction`
+    pub signal_actions: [SignalAction; MAX_SIG + 1],
+    /// Signal number currently being handled, `-1` if none
+    pub handling_sig: isize,
+    /// `TrapContext` saved when diverting into a signal handler, restored by
+    /// `sys_sigreturn`
+    pub trap_ctx_backup: Option<TrapContext>,
+    /// Set by `super::handle_signals` for a fatal, unhandled signal; the
+    /// scheduler does not read it
+    pub killed: bool,
+    /// Set while the task is paused awaiting `SIGCONT`
+    pub frozen: bool,
+
+    // NOTE: 给`sys_mutex_lock_timeout`/`sys_semaphore_down_timeout`这类带
+    // 超时的阻塞原语用：在注册计时器、挂起之前清`false`；如果被`timer::check_timers`
+    // 唤醒（而不是被`unlock`/`up`正常移交）就置`true`，阻塞原语醒来后读它来判断
+    // 这次是拿到了资源还是纯粹超时了。
+    /// Set by [`crate::timer::check_timers`] when this task is woken by a
+    /// timeout rather than by the primitive it was queued on handing off to
+    /// it normally. Reset by the timeout-aware lock/semaphore variants before
+    /// they block, and read by them after `block_current_and_run_next` returns.
+    pub timed_out: bool,
+}
+
+// NOTE: 类比Linux的`vm_area_struct`：只是一个"这段虚拟地址区间将来允许以
+// 某种权限访问"的声明，本身不持有任何物理页帧。
+/// A lazily-backed region of this task's address space
+#[derive(Clone)]
+pub struct VmAreaDescriptor {
+    /// first VPN covered by this region (inclusive)
+    pub vm_start: VirtPageNum,
+    /// one-past-the-last VPN covered by this region (exclusive)
+    pub vm_end: VirtPageNum,
+    /// permission a faulting access into this region must satisfy
+    pub perm: MapPermission,
+}
+
+impl VmAreaDescriptor {
+    /// Describe the region `[start_va, end_va)`, rounded out to whole pages
+    pub fn new(start_va: VirtAddr, end_va: VirtAddr, perm: MapPermission) -> Self {
+        Self {
+            vm_start: start_va.floor(),
+            vm_end: end_va.ceil(),
+            perm,
+        }
+    }
+
+    /// Whether `vpn` falls inside this region
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vm_start <= vpn && vpn < self.vm_end
+    }
 }
 
 // NOTE: 提供的方法主要是对于它内部字段的快捷访问
@@ -103,8 +245,51 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
+    /// Set the stride-scheduling priority, rejecting values below 2
+    pub fn set_priority(&mut self, priority: u64) {
+        assert!(priority >= 2, "priority must be >= 2");
+        self.priority = priority;
+    }
+    /// Milliseconds elapsed since `checkpoint` was last taken, resetting
+    /// `checkpoint` to now
+    pub fn update_checkpoint(&mut self) -> usize {
+        let now = get_time_ms();
+        let elapsed = now - self.checkpoint;
+        self.checkpoint = now;
+        elapsed
+    }
+    /// Find the lazily-backed region containing `vpn`, if any
+    pub fn find_vma(&self, vpn: VirtPageNum) -> Option<&VmAreaDescriptor> {
+        self.vm_areas.iter().find(|vma| vma.contains(vpn))
+    }
+    /// Find the lowest free slot in `fd_table` (extending it if none is
+    /// free) and return its index
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+}
+
+/// The standard fd 0/1/2 table every freshly created process starts with
+fn default_fd_table() -> Vec<Option<Arc<dyn File + Send + Sync>>> {
+    Vec::from([
+        Some(Arc::new(Stdin) as Arc<dyn File + Send + Sync>),
+        Some(Arc::new(Stdout) as Arc<dyn File + Send + Sync>),
+        Some(Arc::new(Stdout) as Arc<dyn File + Send + Sync>),
+    ])
 }
 
+/// Default stride-scheduling priority assigned to freshly created tasks
+pub const DEFAULT_PRIORITY: u64 = 16;
+
+/// Timer ticks a task gets to run before being preempted, see
+/// [`TaskControlBlock::tick`]
+pub const DEFAULT_TIME_SLICE: usize = 10;
+
 impl TaskControlBlock {
     // NOTE: 用来创建一个新进程，目前仅用于内核手动创建唯一一个初始进程`initproc`
     /// Create a new process
@@ -145,6 +330,23 @@ impl TaskControlBlock {
                     exit_code: 0,
                     heap_bottom: user_sp,
                     program_brk: user_sp,
+                    stride: 0,
+                    priority: DEFAULT_PRIORITY,
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    kernel_time: 0,
+                    user_time: 0,
+                    checkpoint: get_time_ms(),
+                    time_slice: DEFAULT_TIME_SLICE,
+                    vm_areas: Vec::new(),
+                    fd_table: default_fd_table(),
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: [SignalAction::default(); MAX_SIG + 1],
+                    handling_sig: -1,
+                    trap_ctx_backup: None,
+                    killed: false,
+                    frozen: false,
+                    timed_out: false,
                 })
             },
         };
@@ -164,14 +366,46 @@ impl TaskControlBlock {
     }
 
     // NOTE: 用来实现`exec`系统调用，即当前进程加载并执行另一个ELF格式可执行文件
-    /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
+    /// Load a new elf to replace the original application address space and
+    /// start execution, passing `args` through as `argv`
+    pub fn exec(&self, elf_data: &[u8], args: Vec<String>) {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
             .unwrap()
             .ppn();
+        let token = memory_set.token();
+
+        // NOTE: 在新地址空间的用户栈顶按从高到低依次放置：先是一个以`NULL`结尾的
+        // 指针数组（每个指针指向对应的字符串），再放每个参数字符串本身
+        // （带结尾`\0`），最后把`user_sp`按`usize`对齐，这样新程序就能以
+        // `main(argc, argv)`的`C`语言约定启动。
+        // push argv pointer array placeholder, to be filled in after we know
+        // where each string ends up
+        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+        let argv_base = user_sp;
+        let mut argv: Vec<_> = (0..=args.len())
+            .map(|arg| {
+                translated_refmut(
+                    token,
+                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
+                )
+            })
+            .collect();
+        *argv[args.len()] = 0;
+        for (i, arg) in args.iter().enumerate() {
+            user_sp -= arg.len() + 1;
+            *argv[i] = user_sp;
+            let mut p = user_sp;
+            for c in arg.as_bytes() {
+                *translated_refmut(token, p as *mut u8) = *c;
+                p += 1;
+            }
+            *translated_refmut(token, p as *mut u8) = 0;
+        }
+        // align downward to an 8-byte boundary
+        user_sp -= user_sp % core::mem::size_of::<usize>();
 
         // **** access current TCB exclusively
         let mut inner = self.inner_exclusive_access();
@@ -179,6 +413,8 @@ impl TaskControlBlock {
         // 这将导致原有地址空间生命周期结束，里面包含的全部物理页帧都会被回收
         // substitute memory_set
         inner.memory_set = memory_set;
+        // the old address space's lazily-backed regions no longer apply
+        inner.vm_areas.clear();
         // update trap_cx ppn
         inner.trap_cx_ppn = trap_cx_ppn;
         // initialize base_size
@@ -194,6 +430,9 @@ impl TaskControlBlock {
             self.kernel_stack.get_top(),
             trap_handler as usize,
         );
+        // a0 = argc, a1 = argv base, per the C `main(argc, argv)` convention
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
         // **** release inner automatically
     }
 
@@ -202,6 +441,8 @@ impl TaskControlBlock {
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
+        // copy fd table
+        let new_fd_table = parent_inner.fd_table.clone();
         // NOTE: 调用`MemorySet::from_existed_user`复制父进程地址空间得到子进程的地址空间
         // copy user space(include trap context)
         let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
@@ -228,9 +469,27 @@ impl TaskControlBlock {
                     exit_code: 0,
                     heap_bottom: parent_inner.heap_bottom,
                     program_brk: parent_inner.program_brk,
+                    stride: 0,
+                    priority: DEFAULT_PRIORITY,
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    kernel_time: 0,
+                    user_time: 0,
+                    checkpoint: get_time_ms(),
+                    time_slice: DEFAULT_TIME_SLICE,
+                    vm_areas: Vec::new(),
+                    fd_table: new_fd_table,
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: [SignalAction::default(); MAX_SIG + 1],
+                    handling_sig: -1,
+                    trap_ctx_backup: None,
+                    killed: false,
+                    frozen: false,
+                    timed_out: false,
                 })
             },
         });
+        insert_into_pid2task(task_control_block.pid.0, &task_control_block);
         // add child
         parent_inner.children.push(task_control_block.clone());
         // modify kernel_sp in trap_cx
@@ -243,6 +502,77 @@ impl TaskControlBlock {
         // ---- release parent PCB
     }
 
+    // NOTE: 用来实现`spawn`系统调用：不同于`fork`+`exec`，这里直接用`elf_data`
+    // 建立一块全新的地址空间（和`TaskControlBlock::new`一样），而不去复制父进程
+    // 现有的地址空间，省掉了`fork`那一次马上就要被`exec`丢弃的深拷贝。
+    /// Create a child process directly from `elf_data`, skipping the
+    /// address-space copy a `fork` immediately followed by `exec` would
+    /// otherwise perform
+    pub fn spawn(self: &Arc<Self>, elf_data: &[u8]) -> Arc<Self> {
+        // ---- access parent PCB exclusively
+        let mut parent_inner = self.inner_exclusive_access();
+        let new_fd_table = parent_inner.fd_table.clone();
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
+            .unwrap()
+            .ppn();
+        // alloc a pid and a kernel stack in kernel space
+        let pid_handle = pid_alloc();
+        let kernel_stack = kstack_alloc();
+        let kernel_stack_top = kernel_stack.get_top();
+        let task_control_block = Arc::new(TaskControlBlock {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    trap_cx_ppn,
+                    base_size: user_sp,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_status: TaskStatus::Ready,
+                    memory_set,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    heap_bottom: user_sp,
+                    program_brk: user_sp,
+                    stride: 0,
+                    priority: DEFAULT_PRIORITY,
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    kernel_time: 0,
+                    user_time: 0,
+                    checkpoint: get_time_ms(),
+                    time_slice: DEFAULT_TIME_SLICE,
+                    vm_areas: Vec::new(),
+                    fd_table: new_fd_table,
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: [SignalAction::default(); MAX_SIG + 1],
+                    handling_sig: -1,
+                    trap_ctx_backup: None,
+                    killed: false,
+                    frozen: false,
+                    timed_out: false,
+                })
+            },
+        });
+        insert_into_pid2task(task_control_block.pid.0, &task_control_block);
+        // add child
+        parent_inner.children.push(task_control_block.clone());
+        // prepare TrapContext in user space
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            kernel_stack_top,
+            trap_handler as usize,
+        );
+        task_control_block
+        // **** release child PCB
+        // ---- release parent PCB
+    }
+
     // NOTE: 以usize的形式返回当前进程的PID
     /// get pid of process
     pub fn getpid(&self) -> usize {
@@ -277,7 +607,7 @@ impl TaskControlBlock {
 }
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+/// task status: UnInit, Ready, Running, Blocked, Zombie
 pub enum TaskStatus {
     /// uninitialized
     UnInit,
@@ -285,6 +615,8 @@ pub enum TaskStatus {
     Ready,
     /// running
     Running,
+    /// parked on a [`super::wait_queue::WaitQueue`], not in the ready queue
+    Blocked,
     /// exited
     Zombie,
 }
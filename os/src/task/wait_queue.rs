@@ -0,0 +1,87 @@
+//! A blocking wait queue for tasks parked on some condition (e.g. pipe
+//! readiness) instead of busy-polling via `suspend_current_and_run_next`.
+
+use super::processor::{current_task, schedule, take_current_task};
+use super::{add_task, TaskContext, TaskControlBlock, TaskStatus};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// FIFO queue of tasks that are `Blocked` on some condition.
+///
+/// Unlike the ready queue, a task parked here is not scheduled again until
+/// something explicitly calls `wake_one`/`wake_all` on this queue.
+pub struct WaitQueue {
+    tasks: Vec<Arc<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    /// Create an empty wait queue
+    pub fn new() -> Self {
+        Self { tasks: Vec::new() }
+    }
+
+    // NOTE: `park_current`只把当前任务登记进等待队列，不触发切换；
+    // 调用者通常还持有某个共享资源（比如管道缓冲区）的锁，
+    // 必须先释放掉那把锁，再调用`block_current_and_run_next`真正让出CPU，
+    // 否则那把锁会在任务挂起期间一直被占着，造成其他任务在同一资源上死锁。
+    /// Register the current task on this wait queue without yet switching
+    /// away. Callers holding an outer lock should drop it before calling
+    /// [`block_current_and_run_next`].
+    pub fn park_current(&mut self) {
+        self.tasks.push(current_task().unwrap());
+    }
+
+    /// Convenience wrapper for callers with no outer lock to release:
+    /// move the current task out of the run queue into this wait queue
+    /// (status `Blocked`) and switch to the idle control flow.
+    pub fn sleep_current(&mut self) {
+        self.park_current();
+        block_current_and_run_next();
+    }
+
+    /// Wake the task that has been waiting the longest, returning it to the
+    /// ready queue.
+    pub fn wake_one(&mut self) {
+        if !self.tasks.is_empty() {
+            let task = self.tasks.remove(0);
+            task.inner_exclusive_access().task_status = TaskStatus::Ready;
+            add_task(task);
+        }
+    }
+
+    /// Wake every task currently waiting, returning them all to the ready
+    /// queue.
+    pub fn wake_all(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.inner_exclusive_access().task_status = TaskStatus::Ready;
+            add_task(task);
+        }
+    }
+
+    /// Whether any task is currently parked on this queue
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Remove `task` from this wait queue if present, without waking it.
+    /// Used to undo a `park_current` registration that turned out to be
+    /// spurious - e.g. a multi-fd `sys_poll` call parks on every watched
+    /// fd's wait queue up front, but once woken only needs to stay
+    /// registered on whichever one(s) actually became ready.
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.tasks.retain(|queued| !Arc::ptr_eq(queued, task));
+    }
+}
+
+/// Take the current task off the processor, mark it `Blocked`, and switch to
+/// the idle control flow. The caller is responsible for having already
+/// registered the task on whichever wait queue should later wake it (see
+/// [`WaitQueue::park_current`]).
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Blocked;
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    drop(task_inner);
+    schedule(task_cx_ptr);
+}
@@ -2,6 +2,12 @@
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use crate::task::{add_task, TaskControlBlock, TaskStatus};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::{Ordering, Reverse};
+use lazy_static::*;
 use riscv::register::time;
 /// The number of ticks per second
 const TICKS_PER_SEC: usize = 100;
@@ -34,7 +40,93 @@ pub fn get_time_us() -> usize {
 // 它首先读取当前 mtime 的值，然后计算出 10ms 之内计数器的增量，
 // 再将 mtimecmp 设置为二者的和。
 // 这样，10ms 之后一个 S 特权级时钟中断就会被触发。
-/// Set the next timer interrupt
+// 如果恰好有一个更早到期的计时器（比如一次很短的 sys_sleep），
+// 就不能傻等到固定的 10ms 之后才触发中断，否则短睡眠会被硬生生拉长到 10ms，
+// 所以这里要和 TIMERS 堆顶比较一下，取更早的那个作为下一次中断的时间。
+/// Set the next timer interrupt, bringing it forward if an earlier-expiring
+/// entry is pending on [`TIMERS`] (so short `sys_sleep`s aren't rounded up to
+/// the next 10ms tick)
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    let tick_deadline = get_time() + CLOCK_FREQ / TICKS_PER_SEC;
+    let deadline = match TIMERS.exclusive_access().peek() {
+        Some(Reverse(entry)) if entry.expire_ticks < tick_deadline => entry.expire_ticks,
+        _ => tick_deadline,
+    };
+    set_timer(deadline);
+}
+
+/// A task parked until `expire_ticks`, ordered by `TIMERS` into a min-heap
+struct TimerEntry {
+    expire_ticks: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ticks == other.expire_ticks
+    }
+}
+impl Eq for TimerEntry {}
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.expire_ticks.cmp(&other.expire_ticks)
+    }
+}
+
+lazy_static! {
+    // NOTE: BinaryHeap 本身是大顶堆，套一层 Reverse 让堆顶变成 expire_ticks 最小
+    // 的那个计时器，这样 check_timers 每次只需要看堆顶就知道有没有到期的任务。
+    /// Min-heap of pending timers, ordered by soonest `expire_ticks` first
+    static ref TIMERS: UPSafeCell<BinaryHeap<Reverse<TimerEntry>>> =
+        unsafe { UPSafeCell::new(BinaryHeap::new()) };
+}
+
+/// Register `task` to be woken once `expire_ms` (an absolute deadline in
+/// milliseconds, e.g. `get_time_ms() + ms`) has passed. Pairs with
+/// `block_current_and_run_next`/`WaitQueue::sleep_current`: the caller is
+/// responsible for actually blocking the task after registering it here.
+pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    let expire_ticks = expire_ms * CLOCK_FREQ / MSEC_PER_SEC;
+    TIMERS
+        .exclusive_access()
+        .push(Reverse(TimerEntry { expire_ticks, task }));
+}
+
+/// Cancel every pending timer registered for `task` (e.g. because it was
+/// already woken by something else, like a `WaitQueue`)
+pub fn remove_timer(task: Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    let remaining = timers
+        .drain()
+        .filter(|Reverse(entry)| !Arc::ptr_eq(&entry.task, &task))
+        .collect();
+    *timers = remaining;
+}
+
+// NOTE: 应当在每次时钟中断里调用一次：把堆顶所有已经到期（expire_ticks <= 当前时间）
+// 的计时器统统弹出并唤醒，而不是只弹一个——多个计时器可能在同一个 tick 内同时到期。
+/// Wake every task whose timer has expired. Meant to be called once per
+/// timer interrupt, alongside `set_next_trigger`.
+pub fn check_timers() {
+    let current_ticks = get_time();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(Reverse(entry)) = timers.peek() {
+        if entry.expire_ticks > current_ticks {
+            break;
+        }
+        let Reverse(entry) = timers.pop().unwrap();
+        {
+            let mut task_inner = entry.task.inner_exclusive_access();
+            task_inner.task_status = TaskStatus::Ready;
+            task_inner.timed_out = true;
+        }
+        drop(timers);
+        add_task(entry.task);
+        timers = TIMERS.exclusive_access();
+    }
 }